@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use bollard::container::{Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions};
+use bollard::Docker;
+use chrono::{DateTime, Utc};
+use crate::config::label;
+use crate::docker::container_info::ContainerInfo;
+use crate::hosts::HostsFileManager;
+use crate::nginx::config_generator::{self, ConfigGenerator};
+use crate::utils::atomic_file::write_atomic;
+use crate::utils::port_mapping::PortMapping;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::time::Instant;
+use tokio::fs;
+use tokio::time::{sleep, Duration};
+
+const BENCH_IMAGE: &str = "nginx:latest";
+const BENCH_DOMAIN_SUFFIX: &str = "autolocalhost-bench.test";
+const POLL_INTERVAL_MS: u64 = 50;
+const PHASE_TIMEOUT_SECS: u64 = 60;
+
+/// Latency samples in milliseconds for each stage of the reconfiguration pipeline
+#[derive(Default)]
+struct Samples {
+    hosts_update: Vec<f64>,
+    config_write: Vec<f64>,
+    nginx_reload: Vec<f64>,
+}
+
+/// Measure how long autolocalhost takes to react to container events.
+/// Uses the running service through the Docker API when a daemon is available,
+/// otherwise runs the configuration pipeline in-process against temporary files.
+pub async fn run(containers: u32, runs: u32) -> Result<()> {
+    if containers == 0 || runs == 0 {
+        return Err(anyhow!("Both --containers and --runs must be at least 1"));
+    }
+
+    let samples = match crate::docker::try_connect_docker().await {
+        Ok(docker) => {
+            println!("Docker daemon available, benchmarking the running service ({} containers x {} runs)", containers, runs);
+            println!("Note: results include the {}s event debounce period", crate::docker::DEBOUNCE_DURATION_SECS);
+            run_docker(&docker, containers, runs).await?
+        }
+        Err(e) => {
+            println!("Docker daemon not available ({}), benchmarking the in-process pipeline", e);
+            run_simulated(containers, runs).await?
+        }
+    };
+
+    print_report(&samples);
+    Ok(())
+}
+
+/// Start labeled throwaway containers and time how long the service takes to pick them up
+async fn run_docker(docker: &Docker, containers: u32, runs: u32) -> Result<Samples> {
+    let mut samples = Samples::default();
+    let hosts_path = HostsFileManager::get_system_hosts_file_path();
+    let config_path = crate::installer::get_nginx_config_dir().join("nginx.conf");
+
+    for run in 0..runs {
+        let names: Vec<String> = (0..containers)
+            .map(|i| format!("autolocalhost-bench-{}-{}", run, i))
+            .collect();
+
+        for name in &names {
+            create_bench_container(docker, name).await?;
+        }
+
+        let started_at = Utc::now();
+        let started = Instant::now();
+        for name in &names {
+            docker.start_container(name, None::<StartContainerOptions<String>>).await?;
+        }
+
+        let result = measure_run(&names, &hosts_path, &config_path, started_at, started).await;
+
+        for name in &names {
+            let options = RemoveContainerOptions { force: true, ..Default::default() };
+            if let Err(e) = docker.remove_container(name, Some(options)).await {
+                warn!("Failed to remove benchmark container {}: {}", name, e);
+            }
+        }
+
+        let (hosts_ms, config_ms, reload_ms) = result?;
+        info!("Run {}: hosts {:.0} ms, config {:.0} ms, reload {:.0} ms", run + 1, hosts_ms, config_ms, reload_ms);
+        samples.hosts_update.push(hosts_ms);
+        samples.config_write.push(config_ms);
+        samples.nginx_reload.push(reload_ms);
+
+        // Let the service process the removals before the next run
+        sleep(Duration::from_secs(crate::docker::DEBOUNCE_DURATION_SECS + 2)).await;
+    }
+
+    Ok(samples)
+}
+
+/// Poll the hosts file, nginx.conf and the state file until all reflect the new containers
+async fn measure_run(
+    names: &[String],
+    hosts_path: &std::path::Path,
+    config_path: &std::path::Path,
+    started_at: DateTime<Utc>,
+    started: Instant,
+) -> Result<(f64, f64, f64)> {
+    let domains: Vec<String> = names.iter().map(|name| bench_domain(name)).collect();
+    let mut hosts_ms = None;
+    let mut config_ms = None;
+    let mut reload_ms = None;
+
+    while hosts_ms.is_none() || config_ms.is_none() || reload_ms.is_none() {
+        if started.elapsed() > Duration::from_secs(PHASE_TIMEOUT_SECS) {
+            return Err(anyhow!(
+                "Timed out after {}s waiting for the service to react; is autolocalhost running?",
+                PHASE_TIMEOUT_SECS
+            ));
+        }
+
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        if hosts_ms.is_none() && file_contains_all(hosts_path, &domains).await {
+            hosts_ms = Some(elapsed_ms);
+        }
+
+        if config_ms.is_none() && file_contains_all(config_path, &domains).await {
+            config_ms = Some(elapsed_ms);
+        }
+
+        // The service writes its state file once ContainerManager::apply returned, whether NGINX
+        // was reloaded in place or its container recreated
+        if reload_ms.is_none() && config_ms.is_some() && state_lists_all(&domains, started_at).await {
+            reload_ms = Some(elapsed_ms);
+        }
+
+        sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+
+    Ok((hosts_ms.unwrap_or_default(), config_ms.unwrap_or_default(), reload_ms.unwrap_or_default()))
+}
+
+async fn create_bench_container(docker: &Docker, name: &str) -> Result<()> {
+    let mut labels = HashMap::new();
+    labels.insert(label("enabled"), String::from("true"));
+    labels.insert(label("domain"), bench_domain(name));
+    labels.insert(label("ports"), String::from("80"));
+
+    let config = Config {
+        image: Some(String::from(BENCH_IMAGE)),
+        labels: Some(labels),
+        ..Default::default()
+    };
+
+    let options = CreateContainerOptions { name: name.to_string(), platform: None };
+    docker.create_container(Some(options), config).await
+        .map_err(|e| anyhow!("Failed to create benchmark container {} from {}: {}", name, BENCH_IMAGE, e))?;
+
+    Ok(())
+}
+
+/// Run the hosts and config stages in-process against temporary files
+async fn run_simulated(containers: u32, runs: u32) -> Result<Samples> {
+    let mut samples = Samples::default();
+    let work_dir = std::env::temp_dir().join(format!("autolocalhost-bench-{}", std::process::id()));
+    fs::create_dir_all(&work_dir).await?;
+
+    let hosts_path = work_dir.join("hosts");
+    let config_path = work_dir.join("nginx.conf");
+    let hosts_manager = HostsFileManager::new(Some(hosts_path.clone()));
+
+    for run in 0..runs {
+        let infos: Vec<ContainerInfo> = (0..containers)
+            .map(|i| {
+                let name = format!("autolocalhost-bench-{}-{}", run, i);
+                ContainerInfo {
+                    id: name.clone(),
+                    domain: bench_domain(&name),
+                    name,
+                    is_running: true,
+                    ports: vec![PortMapping::new(80, 80)],
+                    location_path: String::from("/"),
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let domains: Vec<String> = infos.iter().map(|c| c.domain.clone()).collect();
+
+        fs::write(&hosts_path, "127.0.0.1 localhost\n").await?;
+        let started = Instant::now();
+        hosts_manager.update_managed_block(&domains).await?;
+        samples.hosts_update.push(started.elapsed().as_secs_f64() * 1000.0);
+
+        let started = Instant::now();
+        let config = ConfigGenerator::new(&infos).render_config(config_generator::DEFAULT_TEMPLATE)?;
+        write_atomic(&config_path, config.as_bytes()).await?;
+        samples.config_write.push(started.elapsed().as_secs_f64() * 1000.0);
+    }
+
+    let _ = fs::remove_dir_all(&work_dir).await;
+    Ok(samples)
+}
+
+fn bench_domain(container_name: &str) -> String {
+    format!("{}.{}", container_name, BENCH_DOMAIN_SUFFIX)
+}
+
+async fn file_contains_all(path: &std::path::Path, needles: &[String]) -> bool {
+    match fs::read_to_string(path).await {
+        Ok(content) => needles.iter().all(|needle| content.contains(needle.as_str())),
+        Err(_) => false,
+    }
+}
+
+/// Whether the service finished a reconfiguration after `since` that routes all domains
+async fn state_lists_all(domains: &[String], since: DateTime<Utc>) -> bool {
+    match crate::state::read().await {
+        Ok(state) => {
+            let updated = DateTime::parse_from_rfc3339(&state.updated_at)
+                .map(|time| time.with_timezone(&Utc) > since)
+                .unwrap_or(false);
+            updated && domains.iter().all(|domain| state.domains.contains(domain))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Nearest-rank percentile of an unsorted sample set
+fn percentile(values: &[f64], pct: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn print_report(samples: &Samples) {
+    println!();
+    println!("{:<16} {:>10} {:>10} {:>10}", "stage", "p50 (ms)", "p95 (ms)", "p99 (ms)");
+
+    let stages = [
+        ("hosts update", &samples.hosts_update),
+        ("config write", &samples.config_write),
+        ("nginx reload", &samples.nginx_reload),
+    ];
+
+    for (stage, values) in stages {
+        if values.is_empty() {
+            println!("{:<16} {:>10} {:>10} {:>10}", stage, "n/a", "n/a", "n/a");
+        } else {
+            println!(
+                "{:<16} {:>10.1} {:>10.1} {:>10.1}",
+                stage,
+                percentile(values, 50.0),
+                percentile(values, 95.0),
+                percentile(values, 99.0)
+            );
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::{Result, anyhow};
+use crate::nginx::container_manager::{ContainerManager, NetworkRemoval};
+
+/// Remove the managed NGINX container and network, leaving the service, configuration
+/// and certificates in place
+pub async fn run() -> Result<()> {
+    let docker = crate::docker::try_connect_docker().await
+        .map_err(|e| anyhow!("Docker is not available: {}", e))?;
+    let nginx_manager = ContainerManager::new(docker);
+
+    match nginx_manager.stop_and_remove().await? {
+        0 => println!("NGINX container: none to remove"),
+        count => println!("NGINX container: removed {} container(s)", count),
+    }
+
+    match nginx_manager.remove_network().await? {
+        NetworkRemoval::Removed => println!("Network {}: removed", nginx_manager.network_name()),
+        NetworkRemoval::NotFound => println!("Network {}: not created", nginx_manager.network_name()),
+        NetworkRemoval::InUse(names) => {
+            println!("Network {}: kept, still used by {}", nginx_manager.network_name(), names.join(", "));
+        }
+    }
+
+    println!("A running autolocalhost service recreates them on its next configuration update");
+    Ok(())
+}
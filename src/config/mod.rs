@@ -0,0 +1,422 @@
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+const DEFAULT_LABEL_PREFIX: &str = "kz.byte0.autolocalhost";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Service configuration, read from `config.json` in the config directory.
+/// Every field is optional in the file and falls back to its default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Run the NGINX container with host networking instead of the managed bridge network
+    pub use_host_network: bool,
+    /// Remove an unmanaged container that occupies the NGINX container name instead of failing
+    pub replace_existing_nginx: bool,
+    /// Answer health checks for the proxy itself in every server block
+    pub health_endpoint_enabled: bool,
+    /// Path of the health check location
+    pub health_endpoint_path: String,
+    /// Extra named NGINX access log formats (name -> format string) for the access_log_format label
+    pub log_formats: BTreeMap<String, String>,
+    /// Skip the `<domain>.p12` bundle (certificate, key and CA chain) written next to the PEM files
+    pub no_pkcs12: bool,
+    /// Passphrase protecting the PKCS#12 bundles (empty by default)
+    pub pkcs12_password: String,
+    /// Replace the `Server` response header (an empty value removes it); needs the headers-more module
+    pub server_header: Option<String>,
+    /// Issue leaf certificates for the domain only, without the www./localhost/127.0.0.1 SANs.
+    /// Only affects newly generated certificates.
+    pub cert_minimal_sans: bool,
+    /// Add containers' Docker network aliases to the hosts file and route them like the domain
+    pub hosts_network_aliases: bool,
+    /// Size of the generated DH parameters: 1024 (weak), 2048 or 4096
+    pub dhparam_bits: u32,
+    /// Skip DH parameter generation and leave `ssl_dhparam` out of the config (TLS 1.3 and ECDHE don't use it)
+    pub dhparam_skip: bool,
+    /// Route every running container, deriving `<name>.local` domains for unlabeled ones
+    pub scan_all_containers: bool,
+    /// Write domains to the hosts file; disable when DNS is handled elsewhere
+    pub manage_hosts: bool,
+    /// Preset of http-level NGINX tuning directives
+    pub profile: Profile,
+    /// How the generated nginx.conf reaches the NGINX container
+    pub config_delivery: ConfigDelivery,
+    /// Log which domains and published ports each reconfiguration added or removed
+    pub watch_verbose: bool,
+    /// Create the managed network dual-stack, with an IPv6 subnet next to the IPv4 one
+    pub network_ipv6: bool,
+    /// Prefix of the container labels, e.g. `<prefix>.enabled`; give each instance sharing a Docker host its own
+    pub label_prefix: String,
+    /// Add `reuseport` to the listen sockets so the kernel spreads connections across workers
+    pub listen_reuseport: bool,
+    /// DNS servers of the proxy container, for upstreams only an internal DNS resolves
+    pub nginx_dns: Vec<IpAddr>,
+    /// Image of the proxy container, e.g. `openresty/openresty` for the lua_block label
+    pub nginx_image: String,
+    /// Credentials for pulling the NGINX image from a private registry
+    pub registry_auth: Option<RegistryAuth>,
+    /// Seconds to wait after a container starts before routing to it, for slow initialization
+    pub startup_delay_secs: u64,
+    /// Settings of the NGINX main and events contexts
+    pub nginx: NginxSettings,
+    /// Remove the NGINX container and the managed hosts block when the service stops
+    pub cleanup_on_exit: bool,
+    /// TLS settings shared by all SSL servers
+    pub ssl: SslSettings,
+}
+
+/// `ssl` section of the configuration file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SslSettings {
+    /// Lowest TLS version any SSL server accepts; the ssl_protocols label cannot go below it
+    pub min_version: TlsVersion,
+}
+
+/// TLS protocol version floor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsVersion {
+    #[default]
+    Tls12,
+    Tls13,
+}
+
+impl TlsVersion {
+    /// NGINX `ssl_protocols` value enabling this version and everything newer
+    pub fn protocols(self) -> &'static str {
+        match self {
+            TlsVersion::Tls12 => "TLSv1.2 TLSv1.3",
+            TlsVersion::Tls13 => "TLSv1.3",
+        }
+    }
+
+    /// NGINX protocol name of the version, e.g. `TLSv1.2`
+    pub fn protocol_name(self) -> &'static str {
+        match self {
+            TlsVersion::Tls12 => "TLSv1.2",
+            TlsVersion::Tls13 => "TLSv1.3",
+        }
+    }
+}
+
+/// `nginx` section of the configuration file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NginxSettings {
+    /// Overrides the tuning profile's worker_connections (1-65535)
+    pub worker_connections: Option<u32>,
+    /// `auto` or a number of worker processes
+    pub worker_processes: String,
+}
+
+impl Default for NginxSettings {
+    fn default() -> Self {
+        Self {
+            worker_connections: None,
+            worker_processes: String::from("auto"),
+        }
+    }
+}
+
+/// Delivery of the generated config into the NGINX container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigDelivery {
+    /// Bind-mount the config directory
+    #[default]
+    Mount,
+    /// Copy the config into the container through the Docker archive API, for hosts
+    /// where bind mounts are unreliable (Docker Desktop on Windows/macOS)
+    Copy,
+}
+
+/// Registry login used when pulling the NGINX image. The password is masked in Debug and Display output.
+#[derive(Clone, Deserialize)]
+pub struct RegistryAuth {
+    pub username: String,
+    pub password: String,
+    /// Registry host, e.g. `registry.example.com` (Docker Hub when empty)
+    #[serde(default)]
+    pub server_address: String,
+}
+
+impl fmt::Debug for RegistryAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RegistryAuth")
+            .field("username", &self.username)
+            .field("password", &"****")
+            .field("server_address", &self.server_address)
+            .finish()
+    }
+}
+
+impl fmt::Display for RegistryAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let server = if self.server_address.is_empty() { "Docker Hub" } else { &self.server_address };
+        write!(f, "{}:****@{}", self.username, server)
+    }
+}
+
+/// NGINX tuning preset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// Verbose error log and no client-side caching, so changes show up immediately
+    #[default]
+    Dev,
+    /// More connections, sendfile, gzip, keepalive and open file cache
+    Perf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            use_host_network: false,
+            replace_existing_nginx: false,
+            health_endpoint_enabled: false,
+            health_endpoint_path: String::from("/__autolocalhost_health"),
+            log_formats: BTreeMap::new(),
+            no_pkcs12: false,
+            pkcs12_password: String::new(),
+            server_header: None,
+            cert_minimal_sans: false,
+            hosts_network_aliases: false,
+            dhparam_bits: 2048,
+            dhparam_skip: false,
+            scan_all_containers: false,
+            manage_hosts: true,
+            profile: Profile::default(),
+            config_delivery: ConfigDelivery::default(),
+            watch_verbose: false,
+            network_ipv6: false,
+            label_prefix: String::from(DEFAULT_LABEL_PREFIX),
+            listen_reuseport: false,
+            nginx_dns: Vec::new(),
+            nginx_image: String::from("nginx:latest"),
+            registry_auth: None,
+            startup_delay_secs: 0,
+            nginx: NginxSettings::default(),
+            cleanup_on_exit: false,
+            ssl: SslSettings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Whether the proxy image is OpenResty, which bundles the Lua module
+    pub fn is_openresty_image(&self) -> bool {
+        let repository = self.nginx_image.split(['@', ':']).next().unwrap_or_default();
+        repository.rsplit('/').next().unwrap_or_default().starts_with("openresty")
+    }
+
+    /// Load the configuration file, falling back to defaults when it is missing or invalid
+    pub fn load() -> Self {
+        let path = get_config_file_path();
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<Config>(&content) {
+                Ok(config) => {
+                    info!("Loaded configuration from {}", path.display());
+                    config
+                }
+                Err(e) => {
+                    warn!("Failed to parse configuration file {}: {}. Using defaults.", path.display(), e);
+                    Config::default()
+                }
+            },
+            Err(_) => Config::default(),
+        };
+
+        config.apply_env_overrides();
+        config.normalize_label_prefix();
+        config.validate_nginx_settings();
+        config
+    }
+
+    /// Apply `AUTOLOCALHOST_*` environment variable overrides on top of the file values
+    fn apply_env_overrides(&mut self) {
+        if let Some(value) = env_bool("AUTOLOCALHOST_USE_HOST_NETWORK") {
+            self.use_host_network = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_REPLACE_EXISTING") {
+            self.replace_existing_nginx = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_HEALTH_ENDPOINT") {
+            self.health_endpoint_enabled = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_HEALTH_PATH") {
+            self.health_endpoint_path = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_NO_PKCS12") {
+            self.no_pkcs12 = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_PKCS12_PASSWORD") {
+            self.pkcs12_password = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_SERVER_HEADER") {
+            self.server_header = Some(value);
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_CERT_MINIMAL_SANS") {
+            self.cert_minimal_sans = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_HOSTS_NETWORK_ALIASES") {
+            self.hosts_network_aliases = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_MANAGE_HOSTS") {
+            self.manage_hosts = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_WATCH_VERBOSE") {
+            self.watch_verbose = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_NETWORK_IPV6") {
+            self.network_ipv6 = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_LISTEN_REUSEPORT") {
+            self.listen_reuseport = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_SCAN_ALL_CONTAINERS") {
+            self.scan_all_containers = value;
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_DHPARAM_SKIP") {
+            self.dhparam_skip = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_PROFILE") {
+            match value.trim().to_lowercase().as_str() {
+                "dev" => self.profile = Profile::Dev,
+                "perf" => self.profile = Profile::Perf,
+                _ => warn!("Ignoring invalid value '{}' for AUTOLOCALHOST_PROFILE (expected dev or perf)", value),
+            }
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_SSL_MIN_VERSION") {
+            match value.trim().to_lowercase().as_str() {
+                "tls12" | "tlsv1.2" => self.ssl.min_version = TlsVersion::Tls12,
+                "tls13" | "tlsv1.3" => self.ssl.min_version = TlsVersion::Tls13,
+                _ => warn!("Ignoring invalid value '{}' for AUTOLOCALHOST_SSL_MIN_VERSION (expected tls12 or tls13)", value),
+            }
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_CONFIG_DELIVERY") {
+            match value.trim().to_lowercase().as_str() {
+                "mount" => self.config_delivery = ConfigDelivery::Mount,
+                "copy" => self.config_delivery = ConfigDelivery::Copy,
+                _ => warn!("Ignoring invalid value '{}' for AUTOLOCALHOST_CONFIG_DELIVERY (expected mount or copy)", value),
+            }
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_LABEL_PREFIX") {
+            self.label_prefix = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_NGINX_DNS") {
+            self.nginx_dns = value
+                .split(',')
+                .map(str::trim)
+                .filter(|server| !server.is_empty())
+                .filter_map(|server| match server.parse() {
+                    Ok(ip) => Some(ip),
+                    Err(_) => {
+                        warn!("Ignoring invalid DNS server '{}' in AUTOLOCALHOST_NGINX_DNS (expected an IP address)", server);
+                        None
+                    }
+                })
+                .collect();
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_NGINX_IMAGE") {
+            self.nginx_image = value.trim().to_string();
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_DHPARAM_BITS") {
+            match value.trim().parse() {
+                Ok(bits) => self.dhparam_bits = bits,
+                Err(_) => warn!("Ignoring invalid value '{}' for AUTOLOCALHOST_DHPARAM_BITS", value),
+            }
+        }
+        if let Some(value) = env_bool("AUTOLOCALHOST_CLEANUP_ON_EXIT") {
+            self.cleanup_on_exit = value;
+        }
+        if let Ok(value) = env::var("AUTOLOCALHOST_STARTUP_DELAY_SECS") {
+            match value.trim().parse() {
+                Ok(secs) => self.startup_delay_secs = secs,
+                Err(_) => warn!("Ignoring invalid value '{}' for AUTOLOCALHOST_STARTUP_DELAY_SECS", value),
+            }
+        }
+    }
+
+    /// Drop NGINX settings that would make NGINX refuse the generated config
+    fn validate_nginx_settings(&mut self) {
+        if let Some(connections) = self.nginx.worker_connections {
+            if !(1..=65535).contains(&connections) {
+                warn!("Ignoring nginx.worker_connections {} (expected 1-65535), using the profile default", connections);
+                self.nginx.worker_connections = None;
+            }
+        }
+
+        let processes = self.nginx.worker_processes.trim();
+        let valid = processes == "auto" || processes.parse::<u32>().map(|n| n > 0).unwrap_or(false);
+        if valid {
+            self.nginx.worker_processes = processes.to_string();
+        } else {
+            warn!("Ignoring nginx.worker_processes '{}' (expected auto or a positive number), using auto", processes);
+            self.nginx.worker_processes = String::from("auto");
+        }
+    }
+
+    /// Strip a trailing dot from the label prefix and reject prefixes Docker would not accept as label keys
+    fn normalize_label_prefix(&mut self) {
+        let prefix = self.label_prefix.trim().trim_end_matches('.');
+        let valid = !prefix.is_empty()
+            && prefix.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+
+        if valid {
+            self.label_prefix = prefix.to_string();
+        } else {
+            warn!("Ignoring invalid label prefix '{}', using {}", self.label_prefix, DEFAULT_LABEL_PREFIX);
+            self.label_prefix = String::from(DEFAULT_LABEL_PREFIX);
+        }
+    }
+}
+
+/// Full name of a container label, e.g. `label("domain")` is `kz.byte0.autolocalhost.domain` by default
+pub fn label(name: &str) -> String {
+    format!("{}.{}", get().label_prefix, name)
+}
+
+/// Name of a Docker resource owned by this instance: `base` with the default label prefix,
+/// `base-<prefix>` otherwise, so instances with different prefixes never share one
+pub fn resource_name(base: &str) -> String {
+    let prefix = &get().label_prefix;
+    if prefix == DEFAULT_LABEL_PREFIX {
+        base.to_string()
+    } else {
+        format!("{}-{}", base, prefix)
+    }
+}
+
+/// Get the global configuration, loading it on first access
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::load)
+}
+
+pub fn get_config_file_path() -> PathBuf {
+    crate::installer::get_config_dir().join(CONFIG_FILE_NAME)
+}
+
+/// Read a boolean environment variable ("true"/"1"/"yes" or "false"/"0"/"no")
+pub(crate) fn env_bool(name: &str) -> Option<bool> {
+    let value = env::var(name).ok()?;
+
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        other => {
+            warn!("Ignoring invalid boolean value '{}' for {}", other, name);
+            None
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::{Result, anyhow};
+use crate::hosts::HostsFileManager;
+use similar::TextDiff;
+
+/// Render the hosts block and NGINX config for the current containers and print a unified
+/// diff against the files on disk. Returns whether anything differs; nothing is written.
+pub async fn run() -> Result<bool> {
+    let docker = crate::docker::try_connect_docker().await
+        .map_err(|e| anyhow!("Docker is not available: {}", e))?;
+    let desired = crate::docker::render_desired_state(&docker).await?;
+    let mut changed = false;
+
+    if crate::config::get().manage_hosts {
+        let hosts_manager = HostsFileManager::new(None);
+        let (current, updated) = hosts_manager.preview_managed_block(&desired.hosts_entries).await?;
+        changed |= print_diff(&hosts_manager.hosts_file_path().display().to_string(), &current, &updated);
+    } else {
+        println!("Hosts file management is disabled, skipping the hosts file");
+    }
+
+    let nginx_config_path = crate::installer::get_nginx_config_dir().join("nginx.conf");
+    let current = tokio::fs::read_to_string(&nginx_config_path).await.unwrap_or_default();
+    changed |= print_diff(&nginx_config_path.display().to_string(), &current, &desired.nginx_config);
+
+    if !changed {
+        println!("Hosts file and NGINX config match the current containers");
+    }
+    for lint in &desired.lints {
+        println!("NGINX config lint ({}): {}", lint.severity, lint.message);
+    }
+    Ok(changed)
+}
+
+fn print_diff(path: &str, current: &str, desired: &str) -> bool {
+    match unified_diff(path, current, desired) {
+        Some(diff) => {
+            print!("{}", diff);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Unified diff from the on-disk content to the generated one, or `None` when they are equal
+fn unified_diff(path: &str, current: &str, desired: &str) -> Option<String> {
+    if current == desired {
+        return None;
+    }
+
+    let diff = TextDiff::from_lines(current, desired)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", path), &format!("b/{}", path))
+        .to_string();
+    Some(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_only_changed_content() {
+        assert_eq!(unified_diff("hosts", "a\nb\n", "a\nb\n"), None);
+
+        let diff = unified_diff("hosts", "127.0.0.1 a.test\n", "127.0.0.1 a.test\n127.0.0.1 b.test\n").unwrap();
+        assert!(diff.starts_with("--- a/hosts\n+++ b/hosts\n"));
+        assert!(diff.contains("\n+127.0.0.1 b.test\n"));
+    }
+}
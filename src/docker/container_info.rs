@@ -1,11 +1,41 @@
 use anyhow::{Result, anyhow};
 use bollard::Docker;
-use log::{debug, warn};
+use bollard::models::HealthStatusEnum;
+use base64::Engine;
+use log::{debug, error, info, warn};
 use serde::{Serialize, Deserialize};
-use crate::utils::port_mapping::PortMapping;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::config::{label, TlsVersion};
+use crate::utils::port_mapping::{PortMapping, UpstreamTarget};
+
+/// proxy_read_timeout for WebSocket containers without an explicit timeout, in seconds
+const DEFAULT_WEBSOCKET_TIMEOUT_SECS: u32 = 3600;
+
+/// Environment variables read as a fallback for labels (names under the label prefix) the container does not set
+const ENV_FALLBACKS: &[(&str, &str)] = &[
+    ("AUTOLOCALHOST_ENABLED", "enabled"),
+    ("AUTOLOCALHOST_DOMAIN", "domain"),
+    ("AUTOLOCALHOST_PORTS", "ports"),
+    ("AUTOLOCALHOST_PRIMARY_PORT", "primaryPort"),
+    ("AUTOLOCALHOST_SSL_ENABLED", "sslEnabled"),
+    ("AUTOLOCALHOST_SSL_PORTS", "sslPorts"),
+    ("AUTOLOCALHOST_PATH", "path"),
+    ("AUTOLOCALHOST_STRIP_PATH", "stripPath"),
+    ("AUTOLOCALHOST_GRPC", "grpc"),
+];
+
+/// proxy_cache_valid time for `cache=true`
+const DEFAULT_CACHE_VALID: &str = "10m";
+
+/// Document root for `try_files` when no `try_files_root` label is given (NGINX image default)
+const DEFAULT_TRY_FILES_ROOT: &str = "/usr/share/nginx/html";
 
 /// Container information structure, roughly equivalent to the Node.js ContainerInfo class
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ContainerInfo {
     pub id: String,
     pub name: String,
@@ -13,6 +43,191 @@ pub struct ContainerInfo {
     pub domain: String,
     pub ports: Vec<PortMapping>,
     pub ssl_ports: Vec<PortMapping>,
+    pub grpc_enabled: bool,
+    pub upstream_http2: bool,
+    pub location_path: String,
+    pub strip_path: bool,
+    pub access_log_format: Option<String>,
+    pub proxy_request_buffering: Option<bool>,
+    pub security_headers: SecurityHeaders,
+    pub fallback_upstream: Option<UpstreamTarget>,
+    pub proxy_pass_headers: Vec<String>,
+    pub proxy_hide_headers: Vec<String>,
+    pub upstream_ip: Option<IpAddr>,
+    pub ssl_stapling: bool,
+    pub proxy_connect_timeout: Option<u32>,
+    pub proxy_read_timeout: Option<u32>,
+    pub proxy_send_timeout: Option<u32>,
+    pub ignore_errors: bool,
+    pub network_aliases: Vec<String>,
+    pub client_ca: Option<PathBuf>,
+    pub client_verify_optional: bool,
+    pub proxy_redirect: Option<ProxyRedirectConfig>,
+    pub cors_origin: Option<String>,
+    pub try_files: Vec<String>,
+    pub try_files_root: String,
+    pub upstream_slow_start: Option<Duration>,
+    pub websocket: bool,
+    pub websocket_timeout: Option<u32>,
+    pub disable_proxy_headers: bool,
+    pub ssl_early_data: bool,
+    pub lua_block: Option<String>,
+    pub sub_filters: Vec<(String, String)>,
+    pub mirror: Option<UpstreamTarget>,
+    pub extra_ips: Vec<IpAddr>,
+    pub cache_valid: Option<String>,
+    pub ssl_protocols: Option<String>,
+    pub priority: i32,
+    pub header_map: Option<HeaderMap>,
+}
+
+/// Rewrite of the `Location` header in upstream redirect responses
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ProxyRedirectConfig {
+    /// Map the internal `http://<name>:<port>/` address to `https://<domain>/` on SSL servers
+    Auto,
+    /// Explicit `proxy_redirect <from> <to>` pair, applied to every server
+    Explicit { from: String, to: String },
+}
+
+impl ProxyRedirectConfig {
+    /// Parse the `proxy_pass_rewrite` label: `auto`/`true`, `false`, or `<old_url> <new_url>`
+    fn parse(value: &str) -> Result<Option<Self>> {
+        match value.trim() {
+            "auto" | "true" => Ok(Some(Self::Auto)),
+            "false" | "" => Ok(None),
+            other => match other.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [from, to] => Ok(Some(Self::Explicit { from: from.to_string(), to: to.to_string() })),
+                _ => Err(anyhow!("Invalid proxy_pass_rewrite '{}' (expected auto, false or '<old_url> <new_url>')", other)),
+            },
+        }
+    }
+}
+
+/// Request header set from an NGINX `map` of another variable, from the `map_headers` label,
+/// e.g. `{"header": "X-Tenant-ID", "source": "$host", "target": "tenant", "values": {"a.app.test": "a"}}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderMap {
+    /// Request header sent upstream
+    pub header: String,
+    /// Variable the map reads, without the `$`
+    pub source: String,
+    /// Variable the map declares, without the `$`
+    pub target: String,
+    /// Source value -> header value; keys starting with `~` are regular expressions
+    pub values: BTreeMap<String, String>,
+    /// Header value when no key matches; the header is not sent when this is empty
+    #[serde(default)]
+    pub default: Option<String>,
+}
+
+impl HeaderMap {
+    /// Parse and validate the `map_headers` label JSON
+    fn parse(value: &str) -> Result<Self> {
+        let mut map: Self = serde_json::from_str(value)
+            .map_err(|e| anyhow!("Invalid map_headers '{}': {}", value, e))?;
+        map.source = map.source.trim_start_matches('$').to_string();
+        map.target = map.target.trim_start_matches('$').to_string();
+
+        if !is_valid_header_name(&map.header) {
+            return Err(anyhow!("Invalid map_headers header name '{}'", map.header));
+        }
+        for variable in [&map.source, &map.target] {
+            if !is_valid_variable_name(variable) {
+                return Err(anyhow!("Invalid map_headers variable '${}'", variable));
+            }
+        }
+        if is_reserved_variable_name(&map.target) {
+            return Err(anyhow!("Invalid map_headers target '${}': already defined by NGINX or the template", map.target));
+        }
+        if map.values.is_empty() {
+            return Err(anyhow!("map_headers needs at least one entry in values"));
+        }
+        // Keys and values are emitted inside single quotes
+        let quoted = map.values.iter().flat_map(|(key, value)| [key, value]).chain(map.default.as_ref());
+        for text in quoted {
+            if text.contains(['\'', '\\', '\n', '\r']) {
+                return Err(anyhow!("Invalid map_headers entry '{}': quotes, backslashes and line breaks are not allowed", text));
+            }
+        }
+        // Special parameters of the map block, even when quoted
+        if let Some(key) = map.values.keys().find(|key| ["default", "hostnames", "include", "volatile"].contains(&key.as_str())) {
+            return Err(anyhow!("Invalid map_headers key '{}': reserved by the NGINX map block", key));
+        }
+
+        Ok(map)
+    }
+}
+
+/// Security response headers added to every proxied response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityHeaders {
+    pub content_type_options: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub permissions_policy: Option<String>,
+}
+
+impl SecurityHeaders {
+    /// Build the header set for a `security_headers` preset name
+    fn from_preset(preset: &str) -> Result<Self> {
+        match preset {
+            "strict" => Ok(Self {
+                content_type_options: Some(String::from("nosniff")),
+                frame_options: Some(String::from("DENY")),
+                referrer_policy: Some(String::from("no-referrer")),
+                permissions_policy: Some(String::from("camera=(), microphone=(), geolocation=()")),
+            }),
+            "moderate" => Ok(Self {
+                content_type_options: Some(String::from("nosniff")),
+                frame_options: Some(String::from("SAMEORIGIN")),
+                referrer_policy: Some(String::from("strict-origin-when-cross-origin")),
+                permissions_policy: None,
+            }),
+            "none" => Ok(Self::default()),
+            other => Err(anyhow!("Unknown security_headers preset '{}' (expected strict, moderate or none)", other)),
+        }
+    }
+
+    /// Build the header set from the preset label plus individual header labels
+    fn from_labels(labels: &HashMap<String, String>, container_name: &str) -> Self {
+        let mut headers = match labels.get(&label("security_headers")) {
+            Some(preset) => Self::from_preset(preset.trim()).unwrap_or_else(|e| {
+                warn!("{} for {}", e, container_name);
+                Self::default()
+            }),
+            None => Self::default(),
+        };
+
+        // Individual labels override the preset
+        if let Some(value) = labels.get(&label("content_type_sniffing")) {
+            headers.content_type_options = match value.as_str() {
+                "false" => Some(String::from("nosniff")),
+                _ => None,
+            };
+        }
+
+        if let Some(value) = labels.get(&label("x_frame_options")) {
+            match value.to_lowercase().as_str() {
+                "deny" => headers.frame_options = Some(String::from("DENY")),
+                "sameorigin" => headers.frame_options = Some(String::from("SAMEORIGIN")),
+                "none" => headers.frame_options = None,
+                other => warn!("Ignoring invalid x_frame_options '{}' for {} (expected deny or sameorigin)", other, container_name),
+            }
+        }
+
+        if let Some(value) = labels.get(&label("referrer_policy")) {
+            headers.referrer_policy = parse_header_value(value, "referrer_policy", container_name);
+        }
+
+        if let Some(value) = labels.get(&label("permissions_policy")) {
+            headers.permissions_policy = parse_header_value(value, "permissions_policy", container_name);
+        }
+
+        headers
+    }
 }
 
 impl ContainerInfo {
@@ -31,24 +246,59 @@ impl ContainerInfo {
             None => id.clone(),
         };
 
-        // Check if container is running
+        // Check if container is running; unhealthy containers are not routed to
         let is_running = match details.state {
-            Some(state) => state.running.unwrap_or(false),
+            Some(state) => {
+                let unhealthy = state.health
+                    .and_then(|health| health.status)
+                    .map(|status| status == HealthStatusEnum::UNHEALTHY)
+                    .unwrap_or(false);
+                state.running.unwrap_or(false) && !unhealthy
+            },
             None => false,
         };
 
-        // Extract labels from config
-        let labels = match details.config {
+        // Network aliases (e.g. docker-compose service names), only collected when they are published
+        let network_aliases = if crate::config::get().hosts_network_aliases {
+            details.network_settings
+                .and_then(|settings| settings.networks)
+                .map(|networks| {
+                    let aliases = networks.into_values().flat_map(|network| network.aliases.unwrap_or_default());
+                    collect_network_aliases(aliases, &id, &name)
+                })
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        // Extract labels and exposed ports from config
+        let (mut labels, exposed_ports, env) = match details.config {
             Some(config) => match config.labels {
-                Some(labels) => labels,
+                Some(labels) => (labels, config.exposed_ports.unwrap_or_default(), config.env.unwrap_or_default()),
+                None if crate::config::get().scan_all_containers => {
+                    (HashMap::new(), config.exposed_ports.unwrap_or_default(), config.env.unwrap_or_default())
+                }
                 None => return Err(anyhow!("Container has no labels")),
             },
             None => return Err(anyhow!("Container has no config")),
         };
 
+        // Settings passed with `docker run -e` fill in labels that are not set
+        for label in apply_env_fallbacks(&mut labels, &env) {
+            debug!("Using environment variable for {} on {}", label, name);
+        }
+
+        // Containers without our label are only inspected in scan-all mode
+        let labeled = labels.get(&label("enabled")).map(|v| v == "true").unwrap_or(false);
+
         // Extract domain from labels
-        let domain = match labels.get("kz.byte0.autolocalhost.domain") {
+        let domain = match labels.get(&label("domain")) {
             Some(domain) => domain.clone(),
+            None if !labeled => {
+                let domain = domain_from_name(&name);
+                info!("Container {} has no labels, routing it as {}", name, domain);
+                domain
+            }
             None => {
                 warn!("Container {} has no domain label", name);
                 String::new()
@@ -56,7 +306,7 @@ impl ContainerInfo {
         };
 
         // Parse port mappings
-        let ports_str = labels.get("kz.byte0.autolocalhost.ports")
+        let ports_str = labels.get(&label("ports"))
             .map(|s| s.as_str())
             .unwrap_or("");
 
@@ -69,13 +319,13 @@ impl ContainerInfo {
         };
 
         // Check if SSL is enabled
-        let ssl_enabled = labels.get("kz.byte0.autolocalhost.sslEnabled")
+        let ssl_enabled = labels.get(&label("sslEnabled"))
             .map(|v| v == "true")
             .unwrap_or(false);
 
         // Parse SSL port mappings if enabled
         let ssl_ports = if ssl_enabled {
-            let ssl_ports_str = labels.get("kz.byte0.autolocalhost.sslPorts")
+            let ssl_ports_str = labels.get(&label("sslPorts"))
                 .map(|s| s.as_str())
                 .unwrap_or("");
 
@@ -90,6 +340,335 @@ impl ContainerInfo {
             Vec::new()
         };
 
+        // Internal port the domain proxies to when the container listens on several
+        let primary_port = match labels.get(&label("primaryPort")) {
+            Some(value) => match PortMapping::validate_port(value) {
+                Ok(port) => Some(port),
+                Err(e) => {
+                    warn!("Ignoring primaryPort for {}: {}", name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Without any port labels, fall back to the primary port or the ports the container exposes
+        let ports = if ports.is_empty() && ssl_ports.is_empty() && !labeled {
+            mirror_first_exposed_port(exposed_ports.keys(), &name)
+        } else if ports.is_empty() && ssl_ports.is_empty() {
+            match primary_port {
+                Some(port) => {
+                    info!("No port labels on {}, routing 80:{} from primaryPort", name, port);
+                    vec![PortMapping::new(80, port)]
+                }
+                None => infer_ports_from_exposed(exposed_ports.keys(), &name),
+            }
+        } else {
+            ports
+        };
+
+        let (ports, ssl_ports) = match primary_port {
+            Some(primary) => (
+                only_primary_port(ports, primary, "ports", &name),
+                only_primary_port(ssl_ports, primary, "sslPorts", &name),
+            ),
+            None => (ports, ssl_ports),
+        };
+
+        // Check if the upstream speaks gRPC (proxied with grpc_pass over HTTP/2)
+        let grpc_enabled = labels.get(&label("grpc"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        // Location prefix the container is routed under, and whether it is stripped before proxying
+        let strip_path = labels.get(&label("stripPath"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        let location_path = normalize_location_path(
+            labels.get(&label("path")).map(|s| s.as_str()).unwrap_or("/"),
+            strip_path,
+        );
+
+        // Named log format for a per-domain access log; checked against known formats at render time
+        let access_log_format = match labels.get(&label("access_log_format")) {
+            Some(format) if is_valid_format_name(format) => Some(format.clone()),
+            Some(format) => {
+                warn!("Ignoring invalid access_log_format '{}' for {}", format, name);
+                None
+            }
+            None => None,
+        };
+
+        // Request body buffering; disabling it streams uploads straight to the upstream
+        let proxy_request_buffering = match labels.get(&label("proxy_request_buffering")).map(|v| v.as_str()) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(other) => {
+                warn!("Ignoring invalid proxy_request_buffering value '{}' for {} (expected true or false)", other, name);
+                None
+            }
+            None => None,
+        };
+
+        // Security headers from the preset and individual labels
+        let security_headers = SecurityHeaders::from_labels(&labels, &name);
+
+        // Backup upstream used when the container itself is unavailable
+        let fallback_upstream = match labels.get(&label("fallback_upstream")) {
+            Some(value) => match UpstreamTarget::parse(value) {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    warn!("Failed to parse fallback upstream for {}: {}", name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Secondary upstream that receives a copy of every proxied request
+        let mirror = match labels.get(&label("mirror")) {
+            Some(value) => match UpstreamTarget::parse(value) {
+                Ok(target) => Some(target),
+                Err(e) => {
+                    warn!("Failed to parse mirror for {}: {}", name, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Talk cleartext HTTP/2 (h2c) to the upstream. This goes through NGINX's gRPC module,
+        // which can't rewrite the request URI, so stripPath has no effect.
+        let upstream_http2 = labels.get(&label("upstreamHttp2"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        if upstream_http2 && strip_path {
+            warn!("Container {} sets both upstreamHttp2 and stripPath; the path prefix will not be stripped", name);
+        }
+
+        // Upstream response headers to pass through or hide
+        let proxy_pass_headers = labels.get(&label("proxy_pass_header"))
+            .map(|v| parse_header_list(v, &name))
+            .unwrap_or_default();
+
+        let proxy_hide_headers = labels.get(&label("proxy_hide_header"))
+            .map(|v| parse_header_list(v, &name))
+            .unwrap_or_default();
+
+        // Explicit upstream address, for when the container name doesn't resolve inside NGINX
+        let upstream_ip = labels.get(&label("upstream_ip"))
+            .and_then(|v| parse_upstream_ip(v, &name));
+
+        // Additional IP SANs for the domain certificate, e.g. other loopback addresses
+        let extra_ips = labels.get(&label("extra_ips"))
+            .map(|v| parse_extra_ips(v, &name))
+            .unwrap_or_default();
+
+        // OCSP stapling on the SSL servers
+        let ssl_stapling = labels.get(&label("ssl_stapling"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        if ssl_stapling && ssl_ports.is_empty() {
+            warn!("Container {} sets ssl_stapling but has no SSL ports; it has no effect", name);
+        }
+
+        // Upstream timeouts in seconds; NGINX defaults (60s) apply when unset
+        let proxy_connect_timeout = labels.get(&label("proxy_connect_timeout"))
+            .and_then(|v| parse_timeout(v, "proxy_connect_timeout", &name));
+        let proxy_read_timeout = labels.get(&label("proxy_read_timeout"))
+            .and_then(|v| parse_timeout(v, "proxy_read_timeout", &name));
+        let proxy_send_timeout = labels.get(&label("proxy_send_timeout"))
+            .and_then(|v| parse_timeout(v, "proxy_send_timeout", &name));
+
+        // WebSocket connections are long-lived: unless a timeout is given, keep them open for an hour.
+        // websocket_timeout wins over proxy_read_timeout, which wins over the default.
+        let websocket = labels.get(&label("websocket"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        let websocket_timeout = labels.get(&label("websocket_timeout"))
+            .and_then(|v| match v.trim().parse::<u32>() {
+                Ok(seconds) if (1..=86400).contains(&seconds) => Some(seconds),
+                _ => {
+                    warn!("Ignoring invalid websocket_timeout '{}' for {}: expected seconds between 1 and 86400", v, name);
+                    None
+                }
+            });
+        let proxy_read_timeout = if websocket {
+            websocket_timeout.or(proxy_read_timeout).or(Some(DEFAULT_WEBSOCKET_TIMEOUT_SECS))
+        } else {
+            if websocket_timeout.is_some() {
+                warn!("Container {} sets websocket_timeout without websocket=true; it has no effect", name);
+            }
+            proxy_read_timeout
+        };
+
+        // Serve a stub 200 response instead of 502/503/504 when the upstream is unreachable
+        let ignore_errors = labels.get(&label("ignore_errors"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+
+        // CA (a file on the host) that client certificates are verified against on the SSL servers
+        let client_ca = labels.get(&label("clientCa"))
+            .map(|v| PathBuf::from(v.trim()))
+            .filter(|path| {
+                let exists = path.is_file();
+                if !exists {
+                    warn!("Client CA file {} for {} does not exist, client certificates are not required", path.display(), name);
+                }
+                exists
+            });
+
+        if client_ca.is_some() && ssl_ports.is_empty() {
+            warn!("Container {} sets clientCa but has no SSL ports; it has no effect", name);
+        }
+
+        // Request client certificates without requiring them
+        let client_verify_optional = match labels.get(&label("clientVerify")).map(|v| v.as_str()) {
+            None | Some("on") => false,
+            Some("optional") => true,
+            Some(other) => {
+                warn!("Invalid clientVerify '{}' for {} (expected on or optional), using on", other, name);
+                false
+            }
+        };
+
+        // Rewrite internal upstream URLs in redirect Location headers
+        let proxy_redirect = match labels.get(&label("proxy_pass_rewrite")) {
+            Some(value) => ProxyRedirectConfig::parse(value).unwrap_or_else(|e| {
+                warn!("{} for {}", e, name);
+                None
+            }),
+            None => None,
+        };
+        if proxy_redirect == Some(ProxyRedirectConfig::Auto) && ssl_ports.is_empty() {
+            warn!("Container {} sets proxy_pass_rewrite=auto but has no SSL ports; it has no effect", name);
+        }
+
+        // Allowed CORS origin; `true` allows any origin
+        let cors_origin = labels.get(&label("cors")).and_then(|value| {
+            parse_cors_origin(value).unwrap_or_else(|e| {
+                warn!("{} for {}", e, name);
+                None
+            })
+        });
+
+        // Static file serving with fallbacks, e.g. `$uri $uri/ /index.html` for SPAs. This replaces
+        // proxy_pass for the location: files come from the NGINX container, not from this container.
+        let try_files = match labels.get(&label("try_files")) {
+            Some(value) => parse_try_files(value).unwrap_or_else(|e| {
+                warn!("{} for {}", e, name);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        let try_files_root = if try_files.is_empty() {
+            String::new()
+        } else {
+            if grpc_enabled {
+                warn!("Container {} sets try_files together with grpc; try_files is ignored", name);
+            } else {
+                info!("Container {} serves static files with try_files instead of proxying", name);
+            }
+            match labels.get(&label("try_files_root")).map(|root| root.trim()) {
+                Some(root) if root.starts_with('/') && !root.contains(|c: char| c.is_whitespace() || c == ';') => root.to_string(),
+                Some(root) => {
+                    warn!("Invalid try_files_root '{}' for {} (expected an absolute path), using {}", root, name, DEFAULT_TRY_FILES_ROOT);
+                    String::from(DEFAULT_TRY_FILES_ROOT)
+                }
+                None => String::from(DEFAULT_TRY_FILES_ROOT),
+            }
+        };
+
+        // Ramp traffic up over a warm-up period after the container (re)joins the upstream
+        let upstream_slow_start = labels.get(&label("upstream_slow_start"))
+            .and_then(|value| parse_duration(value, "upstream_slow_start", &name));
+        if upstream_slow_start.is_some() {
+            warn!(
+                "Container {} sets upstream_slow_start, which only NGINX Plus supports; \
+                 open source NGINX rejects the slow_start parameter and will not load the config",
+                name
+            );
+        }
+
+        // Drop the default Host/X-Real-IP/X-Forwarded-* headers, for debugging upstreams that reject them
+        let disable_proxy_headers = labels.get(&label("disable_proxy_headers"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        if disable_proxy_headers {
+            warn!("Container {} disables proxy headers; the upstream no longer sees the original host, client address or scheme", name);
+            if !ssl_ports.is_empty() {
+                warn!("Container {} has SSL ports with disable_proxy_headers; X-Forwarded-Proto is not forwarded over HTTPS", name);
+            }
+        }
+
+        // Per-server TLS protocols, never below the global ssl.min_version
+        let ssl_protocols = labels.get(&label("ssl_protocols"))
+            .and_then(|v| parse_ssl_protocols(v, crate::config::get().ssl.min_version, &name));
+
+        // TLS 1.3 0-RTT on the SSL servers
+        let mut ssl_early_data = labels.get(&label("ssl_early_data"))
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        if ssl_early_data {
+            let protocols = ssl_protocols.as_deref().unwrap_or(crate::config::get().ssl.min_version.protocols());
+            if ssl_ports.is_empty() {
+                warn!("Container {} sets ssl_early_data but has no SSL ports; it has no effect", name);
+            } else if !enables_tls13(protocols) {
+                warn!("Container {} sets ssl_early_data, but its ssl_protocols '{}' lack TLSv1.3; early data is disabled", name, protocols);
+                ssl_early_data = false;
+            } else {
+                warn!(
+                    "Container {} enables TLS 1.3 early data (0-RTT): early requests can be replayed by an attacker, \
+                     only enable it for idempotent endpoints",
+                    name
+                );
+            }
+        }
+
+        // Base64-encoded Lua handler for the location, replacing proxy_pass; needs the OpenResty image
+        let lua_block = labels.get(&label("lua_block")).and_then(|value| {
+            if !crate::config::get().is_openresty_image() {
+                error!(
+                    "Container {} sets lua_block, but the proxy image {} has no Lua module; \
+                     set AUTOLOCALHOST_NGINX_IMAGE=openresty/openresty to use it",
+                    name, crate::config::get().nginx_image
+                );
+                return None;
+            }
+            parse_lua_block(value).map_err(|e| warn!("{} for {}", e, name)).ok()
+        });
+
+        // Response body rewrites as `find|replace` pairs, e.g. hardcoded API URLs in HTML
+        let sub_filters = match labels.get(&label("sub_filter")) {
+            Some(value) => parse_sub_filters(value).unwrap_or_else(|e| {
+                warn!("{} for {}", e, name);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        // Cache upstream responses for this long, e.g. `10m`; `true` uses DEFAULT_CACHE_VALID
+        let cache_valid = labels.get(&label("cache"))
+            .and_then(|v| parse_cache_valid(v, &name));
+
+        // Higher priority containers are emitted first in the generated config
+        let priority = labels.get(&label("priority"))
+            .and_then(|v| match v.trim().parse::<i32>() {
+                Ok(priority) => Some(priority),
+                Err(_) => {
+                    warn!("Ignoring invalid priority '{}' for {}: expected an integer", v, name);
+                    None
+                }
+            })
+            .unwrap_or(0);
+
+        // Request header derived from another variable through an http-level map block
+        let header_map = labels.get(&label("map_headers"))
+            .and_then(|v| HeaderMap::parse(v).map_err(|e| warn!("{} for {}", e, name)).ok());
+
         Ok(ContainerInfo {
             id,
             name,
@@ -97,6 +676,659 @@ impl ContainerInfo {
             domain,
             ports,
             ssl_ports,
+            grpc_enabled,
+            upstream_http2,
+            location_path,
+            strip_path,
+            access_log_format,
+            proxy_request_buffering,
+            security_headers,
+            fallback_upstream,
+            proxy_pass_headers,
+            proxy_hide_headers,
+            upstream_ip,
+            ssl_stapling,
+            proxy_connect_timeout,
+            proxy_read_timeout,
+            proxy_send_timeout,
+            ignore_errors,
+            network_aliases,
+            client_ca,
+            client_verify_optional,
+            proxy_redirect,
+            cors_origin,
+            try_files,
+            try_files_root,
+            upstream_slow_start,
+            websocket,
+            websocket_timeout,
+            disable_proxy_headers,
+            ssl_early_data,
+            lua_block,
+            sub_filters,
+            mirror,
+            extra_ips,
+            cache_valid,
+            ssl_protocols,
+            priority,
+            header_map,
+        })
+    }
+}
+
+/// Usable hostnames among a container's network aliases, without the short container ID
+/// and name that Docker adds as aliases on its own
+fn collect_network_aliases(aliases: impl Iterator<Item = String>, container_id: &str, container_name: &str) -> Vec<String> {
+    let mut result: Vec<String> = aliases
+        .map(|alias| alias.to_lowercase())
+        .filter(|alias| !container_id.starts_with(alias.as_str()) && alias != container_name)
+        .filter(|alias| {
+            let valid = !alias.is_empty()
+                && alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+                && !alias.starts_with(['-', '.']);
+            if !valid {
+                warn!("Ignoring network alias '{}' of {}: not a valid hostname", alias, container_name);
+            }
+            valid
+        })
+        .collect();
+
+    result.sort();
+    result.dedup();
+    result
+}
+
+/// Parse a timeout label value in whole seconds (1-300)
+fn parse_timeout(value: &str, label: &str, container_name: &str) -> Option<u32> {
+    match value.trim().parse::<u32>() {
+        Ok(seconds) if (1..=300).contains(&seconds) => Some(seconds),
+        _ => {
+            warn!("Ignoring invalid {} '{}' for {}: expected seconds between 1 and 300", label, value, container_name);
+            None
+        }
+    }
+}
+
+/// Parse a duration label value such as `30s`, `2m` or `1h` (bare numbers are seconds), up to an hour
+fn parse_duration(value: &str, label: &str, container_name: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1),
+        Some((index, 'm')) => (&value[..index], 60),
+        Some((index, 'h')) => (&value[..index], 3600),
+        _ => (value, 1),
+    };
+
+    match number.parse::<u64>().map(|number| number * multiplier) {
+        Ok(seconds) if (1..=3600).contains(&seconds) => Some(Duration::from_secs(seconds)),
+        _ => {
+            warn!("Ignoring invalid {} '{}' for {}: expected a duration like 30s, 2m or 1h", label, value, container_name);
+            None
+        }
+    }
+}
+
+/// Space- or comma-separated NGINX protocol names. Protocols below the floor are dropped with a
+/// warning; when none remain, the server keeps the global protocols.
+fn parse_ssl_protocols(value: &str, floor: TlsVersion, container_name: &str) -> Option<String> {
+    let floor_rank = protocol_rank(floor.protocol_name()).unwrap_or_default();
+    let mut protocols: Vec<&str> = Vec::new();
+
+    for protocol in value.split([' ', ',']).filter(|p| !p.is_empty()) {
+        match protocol_rank(protocol) {
+            Some(rank) if rank >= floor_rank => {
+                if !protocols.contains(&protocol) {
+                    protocols.push(protocol);
+                }
+            }
+            Some(_) => warn!(
+                "Ignoring {} in ssl_protocols for {}: below the global minimum {}",
+                protocol, container_name, floor.protocol_name()
+            ),
+            None => warn!("Ignoring unknown protocol '{}' in ssl_protocols for {}", protocol, container_name),
+        }
+    }
+
+    if protocols.is_empty() {
+        warn!("No usable ssl_protocols for {}, using {}", container_name, floor.protocols());
+        return None;
+    }
+    Some(protocols.join(" "))
+}
+
+/// Whether an NGINX protocol list includes TLS 1.3, which early data needs
+fn enables_tls13(protocols: &str) -> bool {
+    protocols.split_whitespace().any(|protocol| protocol == "TLSv1.3")
+}
+
+/// Order of the NGINX protocol names, oldest first
+fn protocol_rank(protocol: &str) -> Option<u8> {
+    ["TLSv1", "TLSv1.1", "TLSv1.2", "TLSv1.3"]
+        .iter()
+        .position(|name| *name == protocol)
+        .map(|rank| rank as u8)
+}
+
+/// Validity of cached responses: `true`, or an NGINX time such as `30s`, `10m` or `1h`
+fn parse_cache_valid(value: &str, container_name: &str) -> Option<String> {
+    let value = value.trim();
+    match value {
+        "true" => return Some(String::from(DEFAULT_CACHE_VALID)),
+        "false" | "" => return None,
+        _ => {}
+    }
+
+    let digits = value.trim_end_matches(['s', 'm', 'h', 'd']);
+    let valid = !digits.is_empty()
+        && value.len() - digits.len() <= 1
+        && digits.chars().all(|c| c.is_ascii_digit());
+    if !valid {
+        warn!("Ignoring invalid cache value '{}' for {} (expected true or a time such as 10m)", value, container_name);
+        return None;
+    }
+
+    Some(value.to_string())
+}
+
+/// Comma-separated IP addresses; invalid entries are skipped with a warning
+fn parse_extra_ips(value: &str, container_name: &str) -> Vec<IpAddr> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match IpAddr::from_str(entry) {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                warn!("Ignoring invalid extra_ips entry '{}' for {}", entry, container_name);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Parse an upstream IP label value. Only private (RFC 1918) and loopback addresses
+/// are accepted, since the proxy is meant to reach local containers only.
+fn parse_upstream_ip(value: &str, container_name: &str) -> Option<IpAddr> {
+    let ip: IpAddr = match value.trim().parse() {
+        Ok(ip) => ip,
+        Err(_) => {
+            warn!("Ignoring invalid upstream_ip '{}' for {}", value, container_name);
+            return None;
+        }
+    };
+
+    let is_local = match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback(),
+        IpAddr::V6(ip) => ip.is_loopback(),
+    };
+
+    if !is_local {
+        warn!("Ignoring upstream_ip {} for {}: only private (RFC 1918) and loopback addresses are allowed", ip, container_name);
+        return None;
+    }
+
+    warn!(
+        "Container {} is proxied to the fixed address {}; the container IP may change when it restarts",
+        container_name, ip
+    );
+    Some(ip)
+}
+
+/// Derive an HTTP port mapping from exposed ports (e.g. "3000/tcp"): the lowest exposed
+/// TCP port is proxied from port 80, since routing is done by domain name
+fn infer_ports_from_exposed<'a>(exposed: impl Iterator<Item = &'a String>, container_name: &str) -> Vec<PortMapping> {
+    match first_exposed_tcp_port(exposed) {
+        Some(internal) => {
+            info!("No port labels on {}, inferred mapping 80:{} from exposed ports", container_name, internal);
+            vec![PortMapping::new(80, internal)]
+        }
+        None => {
+            warn!("Container {} has no port labels and exposes no TCP ports", container_name);
+            Vec::new()
+        }
+    }
+}
+
+/// Keep the mappings to the primary internal port. When none of them targets it, the
+/// primaryPort label is likely a typo, so all mappings are kept.
+fn only_primary_port(mappings: Vec<PortMapping>, primary: u16, label_name: &str, container_name: &str) -> Vec<PortMapping> {
+    let (primary_mappings, others): (Vec<PortMapping>, Vec<PortMapping>) = mappings
+        .into_iter()
+        .partition(|mapping| mapping.internal == primary);
+
+    if primary_mappings.is_empty() {
+        if !others.is_empty() {
+            warn!("primaryPort {} of {} matches none of its {} mappings, keeping all of them", primary, container_name, label_name);
+        }
+        return others;
+    }
+
+    for mapping in &others {
+        info!("Ignoring {} mapping {}:{} of {}, primaryPort is {}", label_name, mapping.external, mapping.internal, container_name, primary);
+    }
+    primary_mappings
+}
+
+/// Fill labels missing from the container with their `AUTOLOCALHOST_*` environment variable
+/// (`KEY=VALUE` entries of the container config). Returns the labels taken from the environment.
+fn apply_env_fallbacks(labels: &mut HashMap<String, String>, env: &[String]) -> Vec<String> {
+    let mut applied = Vec::new();
+
+    for entry in env {
+        let Some((key, value)) = entry.split_once('=') else {
+            continue;
+        };
+        let Some(&(_, name)) = ENV_FALLBACKS.iter().find(|(env_key, _)| *env_key == key) else {
+            continue;
+        };
+
+        let label = label(name);
+        if !labels.contains_key(&label) {
+            labels.insert(label.clone(), value.to_string());
+            applied.push(label);
+        }
+    }
+
+    applied
+}
+
+/// Parse the space-separated `try_files` label. Every token is a path or variable
+/// (`$uri`, `/index.html`); the last one may also be a named location or `=<code>`.
+fn parse_try_files(value: &str) -> Result<Vec<String>> {
+    let tokens: Vec<String> = value.split_whitespace().map(String::from).collect();
+    if tokens.len() < 2 {
+        return Err(anyhow!("Invalid try_files '{}' (expected at least a file and a fallback)", value));
+    }
+
+    for (index, token) in tokens.iter().enumerate() {
+        let is_last = index == tokens.len() - 1;
+        let valid = if token.contains([';', '{', '}', '"', '\'']) {
+            false
+        } else if let Some(code) = token.strip_prefix('=') {
+            is_last && code.parse::<u16>().map(|code| (100..=599).contains(&code)).unwrap_or(false)
+        } else if token.starts_with('@') {
+            is_last && token.len() > 1
+        } else {
+            token.starts_with('/') || token.starts_with('$')
+        };
+
+        if !valid {
+            return Err(anyhow!("Invalid try_files token '{}' in '{}'", token, value));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Decode the base64 `lua_block` label. The code ends up inside `content_by_lua_block { }`,
+/// so its braces must balance to not close the block early. Like ngx_lua, braces inside
+/// string literals and comments are not counted.
+fn parse_lua_block(value: &str) -> Result<String> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| anyhow!("Invalid lua_block (expected base64): {}", e))?;
+    let code = String::from_utf8(decoded).map_err(|_| anyhow!("Invalid lua_block: decoded code is not UTF-8"))?;
+
+    if code.trim().is_empty() {
+        return Err(anyhow!("Invalid lua_block: decoded code is empty"));
+    }
+
+    if lua_brace_depth(&code) != Some(0) {
+        return Err(anyhow!("Invalid lua_block: unbalanced braces would break the NGINX config"));
+    }
+
+    Ok(code.trim_end().to_string())
+}
+
+/// Brace depth at the end of Lua code, skipping string literals and comments. `None` when
+/// a closing brace comes before its opening one.
+fn lua_brace_depth(code: &str) -> Option<i32> {
+    let bytes = code.as_bytes();
+    let mut depth = 0i32;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i += 2;
+                i = match long_bracket_level(&bytes[i..]) {
+                    Some(level) => skip_long_bracket(code, i, level),
+                    None => code[i..].find('\n').map_or(bytes.len(), |end| i + end),
+                };
+            }
+            b'[' if long_bracket_level(&bytes[i..]).is_some() => {
+                let level = long_bracket_level(&bytes[i..]).unwrap_or_default();
+                i = skip_long_bracket(code, i, level);
+            }
+            quote @ (b'\'' | b'"') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b'}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return None;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+
+    Some(depth)
+}
+
+/// Level of the Lua long bracket opening at the start of `bytes`: 0 for `[[`, 2 for `[==[`
+fn long_bracket_level(bytes: &[u8]) -> Option<usize> {
+    if bytes.first() != Some(&b'[') {
+        return None;
+    }
+    let level = bytes[1..].iter().take_while(|&&b| b == b'=').count();
+    (bytes.get(level + 1) == Some(&b'[')).then_some(level)
+}
+
+/// Index just past the long bracket opened at `start`, or the end of the code when it is unclosed
+fn skip_long_bracket(code: &str, start: usize, level: usize) -> usize {
+    let body = start + level + 2;
+    let close = format!("]{}]", "=".repeat(level));
+    code[body..].find(&close).map_or(code.len(), |end| body + end + close.len())
+}
+
+/// Parse the `sub_filter` label: comma-separated `find|replace` pairs. Both sides are emitted
+/// inside single quotes, so they must not contain quotes, backslashes or line breaks.
+fn parse_sub_filters(value: &str) -> Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(|pair| {
+            let (find, replace) = pair.split_once('|')
+                .ok_or_else(|| anyhow!("Invalid sub_filter pair '{}' (expected find|replace)", pair.trim()))?;
+            let (find, replace) = (find.trim(), replace.trim());
+
+            if find.is_empty() || replace.is_empty() {
+                return Err(anyhow!("Invalid sub_filter pair '{}': find and replace must not be empty", pair.trim()));
+            }
+            if [find, replace].iter().any(|side| side.contains(['\'', '\\', '\n', '\r', '|'])) {
+                return Err(anyhow!("Invalid sub_filter pair '{}': quotes, backslashes and line breaks are not allowed", pair.trim()));
+            }
+
+            Ok((find.to_string(), replace.to_string()))
+        })
+        .collect()
+}
+
+/// Parse the `cors` label: `true` (any origin), `false`, or a single `http(s)://` origin
+fn parse_cors_origin(value: &str) -> Result<Option<String>> {
+    match value.trim() {
+        "true" | "*" => Ok(Some(String::from("*"))),
+        "false" | "" => Ok(None),
+        origin if (origin.starts_with("http://") || origin.starts_with("https://"))
+            && !origin.contains(|c: char| c.is_whitespace() || c == '"' || c == ';') =>
+        {
+            Ok(Some(origin.trim_end_matches('/').to_string()))
+        }
+        other => Err(anyhow!("Invalid cors origin '{}' (expected true, false or an http(s):// origin)", other)),
+    }
+}
+
+/// Publish the lowest exposed TCP port of an unlabeled container on the same host port
+fn mirror_first_exposed_port<'a>(exposed: impl Iterator<Item = &'a String>, container_name: &str) -> Vec<PortMapping> {
+    match first_exposed_tcp_port(exposed) {
+        Some(port) => {
+            info!("Routing unlabeled container {} on its exposed port {}", container_name, port);
+            vec![PortMapping::new(port, port)]
+        }
+        None => {
+            debug!("Unlabeled container {} exposes no TCP ports, only adding its hosts entry", container_name);
+            Vec::new()
+        }
+    }
+}
+
+/// Lowest TCP port from Docker's exposed port specs (`"8080/tcp"`)
+fn first_exposed_tcp_port<'a>(exposed: impl Iterator<Item = &'a String>) -> Option<u16> {
+    exposed
+        .filter_map(|spec| {
+            let (port, protocol) = spec.split_once('/').unwrap_or((spec.as_str(), "tcp"));
+            if protocol == "tcp" { port.parse().ok() } else { None }
+        })
+        .min()
+}
+
+/// Domain for an unlabeled container: `my_app` becomes `my-app.local`
+fn domain_from_name(name: &str) -> String {
+    format!("{}.local", name.to_lowercase().replace('_', "-"))
+}
+
+/// Normalize a location prefix to start with `/`. When the prefix is stripped it must also
+/// end with `/`, since NGINX replaces the matched prefix with the `proxy_pass` URI path.
+fn normalize_location_path(path: &str, strip_path: bool) -> String {
+    let trimmed = path.trim();
+    let mut normalized = if trimmed.starts_with('/') {
+        trimmed.to_string()
+    } else {
+        format!("/{}", trimmed)
+    };
+
+    if strip_path && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+
+    normalized
+}
+
+/// Parse a comma-separated list of HTTP header names, skipping invalid ones
+fn parse_header_list(value: &str, container_name: &str) -> Vec<String> {
+    value.split(',')
+        .map(|header| header.trim())
+        .filter(|header| !header.is_empty())
+        .filter(|header| {
+            let valid = is_valid_header_name(header);
+            if !valid {
+                warn!("Ignoring invalid header name '{}' for {}", header, container_name);
+            }
+            valid
         })
+        .map(|header| header.to_string())
+        .collect()
+}
+
+/// Parse a header value that is emitted inside double quotes, rejecting characters
+/// that would break out of the directive
+fn parse_header_value(value: &str, label: &str, container_name: &str) -> Option<String> {
+    let trimmed = value.trim();
+
+    if trimmed.is_empty() || trimmed == "none" {
+        return None;
+    }
+
+    if trimmed.contains(['"', ';', '\n', '\r', '\\']) {
+        warn!("Ignoring invalid {} value '{}' for {}", label, trimmed, container_name);
+        return None;
+    }
+
+    Some(trimmed.to_string())
+}
+
+/// Check an NGINX log format name (letters, digits and underscores)
+pub fn is_valid_format_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check an NGINX variable name (without the `$`), e.g. `host` or `http_x_tenant`
+fn is_valid_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Variables the NGINX config already defines, which a map block cannot declare again:
+/// the template's own maps and the built-in variables of the modules it uses
+fn is_reserved_variable_name(name: &str) -> bool {
+    const TEMPLATE: &[&str] = &["connection_upgrade"];
+    const BUILTIN: &[&str] = &[
+        "args", "binary_remote_addr", "body_bytes_sent", "bytes_sent", "connection", "connection_requests",
+        "connection_time", "content_length", "content_type", "document_root", "document_uri", "gzip_ratio",
+        "host", "hostname", "http2", "https", "is_args", "limit_rate", "msec", "nginx_version", "pid", "pipe",
+        "proxy_add_x_forwarded_for", "proxy_host", "proxy_port", "proxy_protocol_addr", "proxy_protocol_port",
+        "query_string", "realip_remote_addr", "realpath_root", "remote_addr", "remote_port", "remote_user",
+        "request", "request_body", "request_body_file", "request_completion", "request_filename", "request_id",
+        "request_length", "request_method", "request_time", "request_uri", "scheme", "server_addr",
+        "server_name", "server_port", "server_protocol", "status", "tcpinfo_rtt", "time_iso8601", "time_local",
+        "uri",
+    ];
+    // Variable families such as $http_<header>, $arg_<name> or $ssl_protocol
+    const BUILTIN_PREFIXES: &[&str] = &["arg_", "cookie_", "http_", "sent_http_", "sent_trailer_", "ssl_", "upstream_"];
+
+    TEMPLATE.contains(&name) || BUILTIN.contains(&name) || BUILTIN_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Check a header name against the HTTP token grammar (RFC 9110)
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| {
+        c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_routing_for_unlabeled_containers() {
+        assert_eq!(domain_from_name("My_App_1"), "my-app-1.local");
+
+        let exposed = [String::from("9000/udp"), String::from("8080/tcp"), String::from("3000/tcp")];
+        let ports = mirror_first_exposed_port(exposed.iter(), "my_app_1");
+        assert_eq!(ports.len(), 1);
+        assert_eq!((ports[0].external, ports[0].internal), (3000, 3000));
+    }
+
+    #[test]
+    fn keeps_ssl_protocols_at_or_above_the_floor() {
+        assert_eq!(parse_ssl_protocols("TLSv1.1 TLSv1.2,TLSv1.3", TlsVersion::Tls12, "app").as_deref(), Some("TLSv1.2 TLSv1.3"));
+        assert_eq!(parse_ssl_protocols("TLSv1.2 TLSv1.3", TlsVersion::Tls13, "app").as_deref(), Some("TLSv1.3"));
+        assert_eq!(parse_ssl_protocols("TLSv1 SSLv3", TlsVersion::Tls12, "app"), None);
+    }
+
+    #[test]
+    fn early_data_needs_tls13_among_the_protocols() {
+        assert!(enables_tls13(TlsVersion::Tls12.protocols()));
+        assert!(enables_tls13("TLSv1.3"));
+        assert!(!enables_tls13("TLSv1.2"));
+    }
+
+    #[test]
+    fn keeps_only_mappings_to_the_primary_port() {
+        let mappings = PortMapping::parse_port_mappings("80:9090,8080:3000").unwrap();
+        let ports = only_primary_port(mappings.clone(), 3000, "ports", "app");
+        assert_eq!(ports.iter().map(|p| (p.external, p.internal)).collect::<Vec<_>>(), vec![(8080, 3000)]);
+
+        // A primary port no mapping targets keeps them all
+        assert_eq!(only_primary_port(mappings, 4000, "ports", "app").len(), 2);
+    }
+
+    #[test]
+    fn parses_proxy_pass_rewrite_label() {
+        assert_eq!(ProxyRedirectConfig::parse("auto").unwrap(), Some(ProxyRedirectConfig::Auto));
+        assert_eq!(ProxyRedirectConfig::parse("false").unwrap(), None);
+        assert_eq!(
+            ProxyRedirectConfig::parse("http://app:8080/  https://app.test/").unwrap(),
+            Some(ProxyRedirectConfig::Explicit {
+                from: String::from("http://app:8080/"),
+                to: String::from("https://app.test/"),
+            })
+        );
+        assert!(ProxyRedirectConfig::parse("http://app:8080/").is_err());
+    }
+
+    #[test]
+    fn labels_take_precedence_over_environment() {
+        let mut labels = HashMap::from([
+            (String::from("kz.byte0.autolocalhost.domain"), String::from("label.test")),
+        ]);
+        let env = vec![
+            String::from("AUTOLOCALHOST_DOMAIN=env.test"),
+            String::from("AUTOLOCALHOST_PORTS=80:3000"),
+            String::from("PATH=/usr/bin"),
+        ];
+
+        assert_eq!(apply_env_fallbacks(&mut labels, &env), vec!["kz.byte0.autolocalhost.ports"]);
+        assert_eq!(labels["kz.byte0.autolocalhost.domain"], "label.test");
+        assert_eq!(labels["kz.byte0.autolocalhost.ports"], "80:3000");
+    }
+
+    #[test]
+    fn parses_slow_start_durations() {
+        assert_eq!(parse_duration("30s", "upstream_slow_start", "app"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m", "upstream_slow_start", "app"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("45", "upstream_slow_start", "app"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("0s", "upstream_slow_start", "app"), None);
+        assert_eq!(parse_duration("2d", "upstream_slow_start", "app"), None);
+    }
+
+    #[test]
+    fn decodes_balanced_lua_blocks() {
+        let encode = |code: &str| base64::engine::general_purpose::STANDARD.encode(code);
+
+        let code = "local t = { status = 200 }\nngx.say(t.status)\n";
+        assert_eq!(parse_lua_block(&encode(code)).unwrap(), code.trim_end());
+
+        assert!(parse_lua_block(&encode("ngx.say('hi') }\nserver {")).is_err());
+
+        // Braces in strings and comments do not count, as in ngx_lua's block parser
+        let code = "ngx.say(\"}\", '{') -- }\n--[[ { ]] local s = [==[ }} ]==]\nngx.say(\"\\\"{\")";
+        assert!(parse_lua_block(&encode(code)).is_ok());
+        assert!(parse_lua_block(&encode("ngx.say('{') }")).is_err());
+        assert!(parse_lua_block(&encode("   ")).is_err());
+        assert!(parse_lua_block("not base64!").is_err());
+    }
+
+    #[test]
+    fn parses_map_headers_label() {
+        let map = HeaderMap::parse(r#"{"header": "X-Tenant-ID", "source": "$host", "target": "tenant", "values": {"a.app.test": "a"}}"#).unwrap();
+        assert_eq!(map.source, "host");
+        assert_eq!(map.target, "tenant");
+        assert_eq!(map.values.get("a.app.test").map(String::as_str), Some("a"));
+        assert_eq!(map.default, None);
+
+        assert!(HeaderMap::parse(r#"{"header": "X-Tenant-ID", "source": "host", "target": "ten-ant", "values": {"a": "b"}}"#).is_err());
+        assert!(HeaderMap::parse(r#"{"header": "X-Tenant-ID", "source": "host", "target": "tenant", "values": {"a": "b'c"}}"#).is_err());
+        assert!(HeaderMap::parse(r#"{"header": "X-Tenant-ID", "source": "host", "target": "tenant", "values": {"default": "b"}}"#).is_err());
+        assert!(HeaderMap::parse(r#"{"header": "X-Tenant-ID", "source": "host", "target": "tenant", "values": {}}"#).is_err());
+
+        // The target must not redefine a template or built-in variable
+        for target in ["connection_upgrade", "host", "remote_addr", "http_x_tenant", "ssl_protocol"] {
+            let value = format!(r#"{{"header": "X-Tenant-ID", "source": "host", "target": "{}", "values": {{"a": "b"}}}}"#, target);
+            assert!(HeaderMap::parse(&value).is_err(), "{} was accepted", target);
+        }
+    }
+
+    #[test]
+    fn parses_sub_filter_pairs() {
+        assert_eq!(
+            parse_sub_filters("http://api.example.com|https://api.local, example.com | app.test").unwrap(),
+            vec![
+                (String::from("http://api.example.com"), String::from("https://api.local")),
+                (String::from("example.com"), String::from("app.test")),
+            ]
+        );
+        assert!(parse_sub_filters("http://api.example.com|").is_err());
+        assert!(parse_sub_filters("no-separator").is_err());
+        assert!(parse_sub_filters("it's|quoted").is_err());
+    }
+
+    #[test]
+    fn validates_try_files_tokens() {
+        assert_eq!(parse_try_files("$uri $uri/ /index.html").unwrap(), vec!["$uri", "$uri/", "/index.html"]);
+        assert!(parse_try_files("$uri =404").is_ok());
+        assert!(parse_try_files("=404 $uri").is_err());
+        assert!(parse_try_files("$uri /index.html;rewrite").is_err());
+        assert!(parse_try_files("$uri").is_err());
     }
 }
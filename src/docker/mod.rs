@@ -1,25 +1,40 @@
 pub mod container_info;
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use bollard::Docker;
 use bollard::container::ListContainersOptions;
+use bollard::errors::Error as BollardError;
 use bollard::system::EventsOptions;
+use crate::config::label;
 use crate::hosts::HostsFileManager;
 use crate::nginx::config_generator::ConfigGenerator;
-use crate::nginx::container_manager::ContainerManager;
+use crate::nginx::config_linter::NginxLint;
+use crate::nginx::container_manager::{ContainerManager, NGINX_CONTAINER_LABEL};
 use crate::ssl::certificate_generator::CertificateGenerator;
+use crate::state::ServiceState;
+use crate::utils::dns_resolver;
 use container_info::ContainerInfo;
+use futures_util::future::join_all;
 use futures_util::StreamExt;
-use log::{info, error, warn};
-use std::collections::{HashMap, HashSet};
+use once_cell::sync::Lazy;
+use log::{debug, info, error, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
 use tokio::sync::oneshot::Receiver;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use std::env;
 
-const TARGET_LABEL: &str = "kz.byte0.autolocalhost.enabled";
-const DEBOUNCE_DURATION_SECS: u64 = 5;
+/// Label (under the configured prefix) that opts a container into routing
+const TARGET_LABEL: &str = "enabled";
+pub const DEBOUNCE_DURATION_SECS: u64 = 5;
+const HOSTS_VERIFY_TIMEOUT_SECS: u64 = 2;
+
+/// Domains and ports routed by the previous reconfiguration, for the watch_verbose change log
+static LAST_ROUTING: Lazy<std::sync::Mutex<RoutingSnapshot>> = Lazy::new(Default::default);
 
 /// Connect to Docker API based on the current platform
 /// Will retry connection every 15 seconds until successful
@@ -67,31 +82,68 @@ pub async fn connect_docker() -> Result<Docker> {
     }
 }
 
+/// Connect to Docker once without retrying, for one-shot commands
+pub async fn try_connect_docker() -> Result<Docker> {
+    let docker = if cfg!(windows) {
+        Docker::connect_with_http_defaults()
+            .context("Failed to connect to Docker over HTTP")?
+    } else {
+        Docker::connect_with_socket_defaults()
+            .context("Failed to connect to Docker socket")?
+    };
+
+    // Test the connection
+    docker.version().await
+        .context("Docker connection test failed")?;
+
+    Ok(docker)
+}
+
 /// State for debouncing configuration updates
 struct DebounceState {
     last_update_request: Option<Instant>,
     pending_update: bool,
 }
 
-/// Monitor Docker containers for events
-pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>) -> Result<()> {
+/// Whether a Docker API call failed because the object no longer exists,
+/// which is expected when a container is removed while we handle its events
+fn is_not_found(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<BollardError>(),
+        Some(BollardError::DockerResponseServerError { status_code: 404, .. })
+    )
+}
+
+/// Whether a container with these labels (or event attributes, which carry the labels) is routed.
+/// In scan-all mode every container is, unless it opts out or is our own NGINX container.
+fn is_managed(labels: Option<&HashMap<String, String>>) -> bool {
+    let enabled = labels.and_then(|labels| labels.get(&label(TARGET_LABEL))).map(|v| v.as_str());
+    if enabled == Some("true") {
+        return true;
+    }
+
+    let own_container = labels.map(|labels| labels.contains_key(&label(NGINX_CONTAINER_LABEL))).unwrap_or(false);
+    crate::config::get().scan_all_containers && enabled.is_none() && !own_container
+}
+
+/// Get all existing containers with our label, or every container in scan-all mode
+async fn scan_containers(docker: &Docker) -> Result<HashMap<String, ContainerInfo>> {
     let mut active_containers = HashMap::new();
-    let debounce_state = Arc::new(Mutex::new(DebounceState {
-        last_update_request: None,
-        pending_update: false,
-    }));
 
-    // First, get all existing containers with our label
     let mut filters = HashMap::new();
-    filters.insert("label".to_string(), vec![format!("{}=true", TARGET_LABEL).to_string()]);
+    if crate::config::get().scan_all_containers {
+        info!("Scanning all existing containers");
+    } else {
+        filters.insert("label".to_string(), vec![format!("{}=true", label(TARGET_LABEL))]);
+        info!("Scanning for existing containers with label {}=true", label(TARGET_LABEL));
+    }
 
     let options = ListContainersOptions {
         all: true,
-        filters: filters.clone(),
+        filters,
         ..Default::default()
     };
 
-    info!("Scanning for existing containers with label {}=true", TARGET_LABEL);
     let containers = docker.list_containers(Some(options)).await?;
 
     for container in containers {
@@ -100,25 +152,60 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
             None => continue,
         };
 
+        if !is_managed(container.labels.as_ref()) {
+            debug!("Skipping container {} without routing", id);
+            continue;
+        }
+
         info!("Found container: {}", id);
-        match ContainerInfo::from_container(&docker, &id).await {
+        match ContainerInfo::from_container(docker, &id).await {
+            // Stopped (or unhealthy) containers are picked up by their start/health events later
+            Ok(container_info) if !container_info.is_running => {
+                debug!("Container {} is not running, leaving it out of the active set", container_info.name);
+            },
             Ok(container_info) => {
                 active_containers.insert(id, container_info);
             },
+            Err(e) if is_not_found(&e) => {
+                debug!("Container {} was removed during the scan", id);
+            },
             Err(e) => {
                 warn!("Failed to get container info for {}: {}", id, e);
             }
         }
     }
 
+    Ok(active_containers)
+}
+
+/// Scan containers and apply the configuration once, for commands that change
+/// settings outside the running service
+pub async fn reconfigure_once(docker: &Docker) -> Result<()> {
+    let active_containers = scan_containers(docker).await?;
+    update_configuration(docker, &active_containers).await
+}
+
+/// Monitor Docker containers for events
+pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>) -> Result<()> {
+    let mut active_containers = HashMap::new();
+    let debounce_state = Arc::new(Mutex::new(DebounceState {
+        last_update_request: None,
+        pending_update: false,
+    }));
+
+    // First, get all existing containers with our label
+    active_containers.extend(scan_containers(&docker).await?);
+
     // Update configuration based on initial containers
     update_configuration(&docker, &active_containers).await?;
 
     // Set up event monitoring
     let mut event_filters = HashMap::new();
     event_filters.insert("type".to_string(), vec!["container".to_string()]);
-    event_filters.insert("event".to_string(), vec!["start".to_string(), "stop".to_string(), "die".to_string(), "destroy".to_string()]);
-    event_filters.insert("label".to_string(), vec![format!("{}=true", TARGET_LABEL).to_string()]);
+    event_filters.insert("event".to_string(), vec!["start".to_string(), "stop".to_string(), "die".to_string(), "destroy".to_string(), "rename".to_string(), "health_status".to_string()]);
+    if !crate::config::get().scan_all_containers {
+        event_filters.insert("label".to_string(), vec![format!("{}=true", label(TARGET_LABEL))]);
+    }
 
     let opts = EventsOptions {
         filters: event_filters,
@@ -129,30 +216,60 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
     let mut events = docker.events(Some(opts));
     let mut shutdown_future = shutdown_rx;
 
+    // Containers waiting out the startup delay; the timer sends the ID back to this loop
+    let startup_delay = crate::config::get().startup_delay_secs;
+    let (delayed_start_tx, mut delayed_start_rx) = mpsc::unbounded_channel::<String>();
+    let mut pending_starts: HashMap<String, JoinHandle<()>> = HashMap::new();
+
     // Spawn debounce task
     let docker_clone = docker.clone();
     let active_containers_arc = Arc::new(Mutex::new(active_containers.clone()));
     let active_containers_for_task = active_containers_arc.clone();
     let debounce_state_clone = debounce_state.clone();
+    let debounce_cancel = CancellationToken::new();
+    let debounce_cancel_for_task = debounce_cancel.clone();
+
+    let debounce_task = tokio::spawn(async move {
+        let mut maintenance_enabled = crate::maintenance::is_enabled();
 
-    tokio::spawn(async move {
         loop {
-            sleep(Duration::from_secs(1)).await;
+            tokio::select! {
+                _ = sleep(Duration::from_secs(1)) => {},
+                _ = debounce_cancel_for_task.cancelled() => {
+                    info!("Debounce task stopped");
+                    break;
+                }
+            }
 
+            // Checked under the state lock: once shutdown has cancelled the token, no new update starts
             let mut state = debounce_state_clone.lock().await;
-            if state.pending_update {
-                if let Some(last_request) = state.last_update_request {
-                    if last_request.elapsed() >= Duration::from_secs(DEBOUNCE_DURATION_SECS) {
-                        info!("Debounce period elapsed, triggering configuration update");
-                        state.pending_update = false;
-                        state.last_update_request = None;
-                        drop(state);
-
-                        let containers = active_containers_for_task.lock().await;
-                        if let Err(e) = update_configuration(&docker_clone, &containers).await {
-                            error!("Failed to update configuration: {}", e);
-                        }
-                    }
+            if debounce_cancel_for_task.is_cancelled() {
+                info!("Debounce task stopped");
+                break;
+            }
+
+            // `autolocalhost maintenance on|off` only toggles the flag file, so apply it from here
+            let maintenance = crate::maintenance::is_enabled();
+            if maintenance != maintenance_enabled {
+                maintenance_enabled = maintenance;
+                info!("Maintenance mode turned {}, triggering configuration update", if maintenance { "on" } else { "off" });
+                state.pending_update = true;
+                state.last_update_request = None;
+            }
+
+            // Container events wait out the debounce period, a maintenance toggle applies right away
+            let due = state.pending_update && state.last_update_request
+                .map(|last_request| last_request.elapsed() >= Duration::from_secs(DEBOUNCE_DURATION_SECS))
+                .unwrap_or(true);
+            if due {
+                info!("Triggering configuration update");
+                state.pending_update = false;
+                state.last_update_request = None;
+                drop(state);
+
+                let containers = active_containers_for_task.lock().await;
+                if let Err(e) = update_configuration(&docker_clone, &containers).await {
+                    error!("Failed to update configuration: {}", e);
                 }
             }
         }
@@ -160,11 +277,28 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
 
     loop {
         tokio::select! {
+            // Shutdown wins over events that arrive in the same tick
+            biased;
+
+            _ = &mut shutdown_future => {
+                info!("Shutting down container monitoring");
+                break;
+            }
+            Some(id) = delayed_start_rx.recv() => {
+                // A stop event may have cancelled the delay after the timer already fired
+                if pending_starts.remove(&id).is_some() && add_started_container(&docker, &id, &mut active_containers).await {
+                    schedule_update(&active_containers, &active_containers_arc, &debounce_state).await;
+                }
+            }
             Some(event_result) = events.next() => {
                 match event_result {
                     Ok(event) => {
                         if let Some(actor) = event.actor {
                             if let Some(id) = actor.id {
+                                if !is_managed(actor.attributes.as_ref()) {
+                                    continue;
+                                }
+
                                 if let Some(action) = event.action {
                                     info!("Container event: {} - {}", id, action);
 
@@ -172,28 +306,93 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
 
                                     match action.as_str() {
                                         "start" => {
-                                            // Check if container is already in active list
-                                            if !active_containers.contains_key(&id) {
-                                                match ContainerInfo::from_container(&docker, &id).await {
-                                                    Ok(container_info) => {
-                                                        active_containers.insert(id.clone(), container_info);
-                                                        state_changed = true;
-                                                        info!("Container {} added to active list", id);
-                                                    },
-                                                    Err(e) => warn!("Failed to get container info: {}", e)
+                                            if startup_delay > 0 && !active_containers.contains_key(&id) {
+                                                let delayed_start_tx = delayed_start_tx.clone();
+                                                let delayed_id = id.clone();
+                                                let timer = tokio::spawn(async move {
+                                                    sleep(Duration::from_secs(startup_delay)).await;
+                                                    let _ = delayed_start_tx.send(delayed_id);
+                                                });
+                                                if let Some(previous) = pending_starts.insert(id.clone(), timer) {
+                                                    previous.abort();
                                                 }
+                                                info!("Container {} started, routing it after the {}s startup delay", id, startup_delay);
                                             } else {
-                                                info!("Container {} already in active list, ignoring start event", id);
+                                                state_changed = add_started_container(&docker, &id, &mut active_containers).await;
                                             }
                                         },
                                         "stop" | "die" | "destroy" => {
+                                            if action == "die" {
+                                                log_container_exit(&id, actor.attributes.as_ref());
+                                            }
+
+                                            if let Some(timer) = pending_starts.remove(&id) {
+                                                timer.abort();
+                                                info!("Container {} {} during its startup delay, it will not be routed", id, action);
+                                            }
+
                                             // Check if container is actually in active list before removing
                                             if active_containers.contains_key(&id) {
                                                 active_containers.remove(&id);
                                                 state_changed = true;
                                                 info!("Container {} removed from active list", id);
                                             } else {
-                                                info!("Container {} already removed from active list, ignoring {} event", id, action);
+                                                debug!("Container {} already removed from active list, ignoring {} event", id, action);
+                                            }
+                                        },
+                                        "rename" => {
+                                            // The map is keyed by ID, so replace the entry with a fresh inspect carrying the new name
+                                            if active_containers.remove(&id).is_some() {
+                                                let old_name = actor.attributes.as_ref()
+                                                    .and_then(|attrs| attrs.get("oldName"))
+                                                    .map(|n| n.trim_start_matches('/').to_string())
+                                                    .unwrap_or_default();
+
+                                                match ContainerInfo::from_container(&docker, &id).await {
+                                                    Ok(container_info) => {
+                                                        info!("Container {} renamed from {} to {}", id, old_name, container_info.name);
+                                                        active_containers.insert(id.clone(), container_info);
+                                                    },
+                                                    Err(e) => warn!("Failed to get container info after rename: {}", e)
+                                                }
+                                                state_changed = true;
+                                            } else {
+                                                info!("Container {} is not in active list, ignoring rename event", id);
+                                            }
+                                        },
+                                        health if health.starts_with("health_status") => {
+                                            // Actions look like "health_status: unhealthy"; "starting" keeps the current routing
+                                            let routable = match health.split_once(':').map(|(_, status)| status.trim()) {
+                                                Some("healthy") => Some(true),
+                                                Some("unhealthy") => Some(false),
+                                                _ => None,
+                                            };
+
+                                            // Containers that were already unhealthy at startup are not in the active list yet
+                                            if routable == Some(true) && !active_containers.contains_key(&id) && !pending_starts.contains_key(&id) {
+                                                match ContainerInfo::from_container(&docker, &id).await {
+                                                    Ok(container_info) if container_info.is_running => {
+                                                        info!("Container {} is healthy, adding it to routing", container_info.name);
+                                                        active_containers.insert(id.clone(), container_info);
+                                                        state_changed = true;
+                                                    },
+                                                    Ok(_) => {},
+                                                    Err(e) if is_not_found(&e) => debug!("Container {} was removed before it could be inspected", id),
+                                                    Err(e) => warn!("Failed to get container info: {}", e)
+                                                }
+                                            }
+
+                                            // Unhealthy containers stay in the active list but are left out of routing
+                                            if let (Some(routable), Some(container)) = (routable, active_containers.get_mut(&id)) {
+                                                if container.is_running != routable {
+                                                    container.is_running = routable;
+                                                    state_changed = true;
+                                                    if routable {
+                                                        info!("Container {} is healthy again, restoring it to routing", container.name);
+                                                    } else {
+                                                        warn!("Container {} is unhealthy, removing it from routing", container.name);
+                                                    }
+                                                }
                                             }
                                         },
                                         _ => {}
@@ -201,16 +400,7 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
 
                                     // Request configuration update only if state actually changed
                                     if state_changed {
-                                        // Update the shared containers state
-                                        let mut shared_containers = active_containers_arc.lock().await;
-                                        *shared_containers = active_containers.clone();
-                                        drop(shared_containers);
-
-                                        // Request debounced update
-                                        let mut state = debounce_state.lock().await;
-                                        state.last_update_request = Some(Instant::now());
-                                        state.pending_update = true;
-                                        info!("Configuration update scheduled (debounced)");
+                                        schedule_update(&active_containers, &active_containers_arc, &debounce_state).await;
                                     }
                                 }
                             }
@@ -221,81 +411,491 @@ pub async fn monitor_containers(docker: Arc<Docker>, shutdown_rx: Receiver<()>)
                     }
                 }
             },
-            _ = &mut shutdown_future => {
-                info!("Shutting down container monitoring");
-                break;
-            }
         }
     }
 
+    for (_, timer) in pending_starts.drain() {
+        timer.abort();
+    }
+
+    // Stop the debounce task before anything else, then drop the update it has not started yet.
+    // An update already in progress is allowed to finish before this function returns.
+    info!("Shutdown: stopping configuration updates");
+    debounce_cancel.cancel();
+    let mut state = debounce_state.lock().await;
+    if state.pending_update {
+        info!("Discarding the pending configuration update on shutdown");
+        state.pending_update = false;
+        state.last_update_request = None;
+    }
+    drop(state);
+
+    info!("Shutdown: waiting for a configuration update in progress");
+    if let Err(e) = debounce_task.await {
+        error!("Debounce task failed: {}", e);
+    }
+
+    if crate::config::get().cleanup_on_exit {
+        clean_up_on_exit(&docker).await;
+    } else {
+        info!("Shutdown: leaving the NGINX container and hosts entries in place");
+    }
+
     Ok(())
 }
 
-/// Update configuration based on active containers
-async fn update_configuration(docker: &Docker, containers: &HashMap<String, ContainerInfo>) -> Result<()> {
-    info!("Updating configuration with {} containers", containers.len());
+/// Remove the NGINX container and the managed hosts block, so nothing keeps routing to
+/// containers after the service stopped
+async fn clean_up_on_exit(docker: &Docker) {
+    info!("Shutdown: removing the NGINX container");
+    match ContainerManager::new(docker.clone()).stop_and_remove().await {
+        Ok(count) => info!("Removed {} NGINX container(s)", count),
+        Err(e) => warn!("Failed to remove the NGINX container: {}", e),
+    }
 
-    // Filter out containers that aren't running
-    let running_containers: Vec<ContainerInfo> = containers.values()
-        .filter(|c| c.is_running)
-        .cloned()
-        .collect();
+    if crate::config::get().manage_hosts {
+        info!("Shutdown: removing the managed hosts block");
+        // An empty domain list removes the block
+        if let Err(e) = HostsFileManager::new(None).update_managed_block(&[]).await {
+            warn!("Failed to remove the managed hosts block: {}", e);
+        }
+    }
+}
 
-    // Extract domains for hosts file
-    let mut domains = Vec::new();
-    let mut external_ports = HashSet::new();
+/// Inspect a started container and add it to the active list. Returns whether the list changed.
+async fn add_started_container(docker: &Docker, id: &str, active_containers: &mut HashMap<String, ContainerInfo>) -> bool {
+    if active_containers.contains_key(id) {
+        info!("Container {} already in active list, ignoring start event", id);
+        return false;
+    }
 
-    for container in &running_containers {
-        // Check for duplicate domains
-        if domains.contains(&container.domain) {
-            return Err(anyhow!("Duplicate domain name in container {}", container.name));
+    match ContainerInfo::from_container(docker, id).await {
+        Ok(container_info) => {
+            active_containers.insert(id.to_string(), container_info);
+            info!("Container {} added to active list", id);
+            true
+        },
+        Err(e) if is_not_found(&e) => {
+            debug!("Container {} was removed before it could be inspected", id);
+            false
+        },
+        Err(e) => {
+            warn!("Failed to get container info: {}", e);
+            false
+        }
+    }
+}
+
+/// Share the active list with the debounce task and request a debounced update
+async fn schedule_update(
+    active_containers: &HashMap<String, ContainerInfo>,
+    shared_containers: &Mutex<HashMap<String, ContainerInfo>>,
+    debounce_state: &Mutex<DebounceState>,
+) {
+    *shared_containers.lock().await = active_containers.clone();
+
+    let mut state = debounce_state.lock().await;
+    state.last_update_request = Some(Instant::now());
+    state.pending_update = true;
+    info!("Configuration update scheduled (debounced)");
+}
+
+/// Log how a container exited, based on the `exitCode` attribute of a `die` event
+fn log_container_exit(id: &str, attributes: Option<&HashMap<String, String>>) {
+    let name = attributes
+        .and_then(|attrs| attrs.get("name"))
+        .map(|n| n.as_str())
+        .unwrap_or(id);
+
+    let exit_code = attributes
+        .and_then(|attrs| attrs.get("exitCode"))
+        .and_then(|code| code.parse::<i64>().ok());
+
+    match exit_code {
+        Some(0) => info!("Container {} stopped cleanly (exit code 0)", name),
+        Some(code) => warn!("Container {} crashed with exit code {}", name, code),
+        None => info!("Container {} died without a reported exit code", name),
+    }
+}
+
+/// Check that the local resolver sees the hosts file entries we just wrote. The domains are
+/// resolved concurrently, so the check holds up an update for at most one timeout.
+async fn verify_hosts_entries(domains: &[String]) {
+    let loopback = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let limit = Duration::from_secs(HOSTS_VERIFY_TIMEOUT_SECS);
+
+    let resolved = join_all(domains.iter().map(|domain| dns_resolver::wait_for_hosts_entry(domain, loopback, limit))).await;
+    for (domain, resolved) in domains.iter().zip(resolved) {
+        if !resolved {
+            warn!(
+                "{} does not resolve to {} after updating the hosts file; the system resolver cache may need flushing",
+                domain, loopback
+            );
+        }
+    }
+}
+
+/// Domains, published ports and certificates needed for a set of running containers
+#[derive(Debug, Default)]
+struct RoutingPlan {
+    domains: Vec<String>,
+    external_ports: HashSet<u16>,
+    ssl_domains: Vec<String>,
+    // Network aliases published to the hosts file next to the domains
+    alias_domains: Vec<String>,
+}
+
+/// What a reconfiguration routes, compared between updates in watch_verbose mode
+#[derive(Debug, Default, Clone, PartialEq)]
+struct RoutingSnapshot {
+    domains: BTreeSet<String>,
+    ports: BTreeSet<u16>,
+}
+
+impl RoutingSnapshot {
+    /// Added (`+`) and removed (`-`) domains and ports relative to a previous snapshot
+    fn changes_since(&self, previous: &RoutingSnapshot) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        changes.extend(self.domains.difference(&previous.domains).map(|domain| format!("+ domain {}", domain)));
+        changes.extend(previous.domains.difference(&self.domains).map(|domain| format!("- domain {}", domain)));
+        changes.extend(self.ports.difference(&previous.ports).map(|port| format!("+ port {}", port)));
+        changes.extend(previous.ports.difference(&self.ports).map(|port| format!("- port {}", port)));
+
+        changes
+    }
+}
+
+/// Log the routing changes since the previous reconfiguration
+fn log_routing_changes(current: RoutingSnapshot) {
+    let mut last = LAST_ROUTING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let changes = current.changes_since(&last);
+
+    if changes.is_empty() {
+        info!("Reconfiguration changed no domains or ports");
+    } else {
+        info!("Reconfiguration changes:");
+        for change in changes {
+            info!("  {}", change);
+        }
+    }
+
+    *last = current;
+}
+
+/// Drop plain HTTP ports that the same container also lists as SSL ports: NGINX rejects
+/// an `ssl` and a plain server listening on the same port for one name. Returns the dropped ports.
+fn drop_http_ports_overlapping_ssl(container: &mut ContainerInfo) -> Vec<u16> {
+    let ssl_external: HashSet<u16> = container.ssl_ports.iter().map(|port| port.external).collect();
+    let mut dropped = Vec::new();
+
+    container.ports.retain(|port| {
+        let overlaps = ssl_external.contains(&port.external);
+        if overlaps {
+            dropped.push(port.external);
+        }
+        !overlaps
+    });
+
+    dropped
+}
+
+/// Collect what the hosts file, certificates and NGINX container need from the running
+/// containers. HTTP and SSL ports are handled independently, so a container that only
+/// declares SSL ports still gets its domain, its published ports and a certificate.
+/// Containers may share a domain under distinct location paths.
+fn plan_routing(running_containers: &[ContainerInfo]) -> Result<RoutingPlan> {
+    let mut plan = RoutingPlan::default();
+    let mut routes = HashSet::new();
+
+    for container in running_containers {
+        // Check for duplicate routes; gRPC containers are always served from /
+        let path = if container.grpc_enabled { "/" } else { container.location_path.as_str() };
+        for port in container.ports.iter().chain(&container.ssl_ports) {
+            if !container.domain.is_empty() && !routes.insert((container.domain.as_str(), port.external, path)) {
+                return Err(anyhow!(
+                    "Duplicate route {}:{}{} in container {}",
+                    container.domain, port.external, path, container.name
+                ));
+            }
         }
 
         // Add domain to list
-        if !container.domain.is_empty() {
-            domains.push(container.domain.clone());
+        if !container.domain.is_empty() && !plan.domains.contains(&container.domain) {
+            plan.domains.push(container.domain.clone());
         }
 
         // Collect all external ports from container
-        for port in &container.ports {
-            external_ports.insert(port.external);
+        for port in container.ports.iter().chain(&container.ssl_ports) {
+            plan.external_ports.insert(port.external);
         }
 
-        for ssl_port in &container.ssl_ports {
-            external_ports.insert(ssl_port.external);
+        // One certificate per domain, however many SSL ports it listens on
+        if !container.ssl_ports.is_empty() && !container.domain.is_empty() && !plan.ssl_domains.contains(&container.domain) {
+            plan.ssl_domains.push(container.domain.clone());
+        }
 
-            // Generate SSL certificate if needed
-            if !container.domain.is_empty() {
-                let cert_gen = CertificateGenerator::new(&container.domain);
-                if let Err(e) = cert_gen.generate_certificates().await {
-                    warn!("Failed to generate SSL certificate for {}: {}", container.domain, e);
-                }
+        plan.alias_domains.extend(container.network_aliases.iter().cloned());
+    }
+
+    // Aliases never shadow a domain and may be shared between containers
+    plan.alias_domains.retain(|alias| !plan.domains.contains(alias));
+    plan.alias_domains.sort();
+    plan.alias_domains.dedup();
+
+    Ok(plan)
+}
+
+/// Hosts entries and NGINX config the service would apply for the current containers
+pub struct DesiredState {
+    pub hosts_entries: Vec<String>,
+    pub nginx_config: String,
+    pub lints: Vec<NginxLint>,
+}
+
+/// Render what `update_configuration` would write, without writing or starting anything
+pub async fn render_desired_state(docker: &Docker) -> Result<DesiredState> {
+    let containers = scan_containers(docker).await?;
+    let running_containers = routable_containers(&containers);
+    let plan = plan_routing(&running_containers)?;
+
+    let (nginx_config, lints) = ConfigGenerator::new(&running_containers).render_with_lints().await?;
+
+    Ok(DesiredState {
+        hosts_entries: plan.domains.into_iter().chain(plan.alias_domains).collect(),
+        nginx_config,
+        lints,
+    })
+}
+
+/// extra_ips of every container routed to a domain, the IP SANs of its certificate
+fn domain_extra_ips(containers: &[ContainerInfo], domain: &str) -> Vec<IpAddr> {
+    containers.iter()
+        .filter(|container| container.domain == domain)
+        .flat_map(|container| container.extra_ips.iter().copied())
+        .collect()
+}
+
+/// extra_ips of the running containers routed to a domain
+pub async fn running_extra_ips(docker: &Docker, domain: &str) -> Result<Vec<IpAddr>> {
+    let containers = scan_containers(docker).await?;
+    Ok(domain_extra_ips(&routable_containers(&containers), domain))
+}
+
+/// Running containers, with HTTP ports that are also listed as SSL ports dropped. Sorted by
+/// domain, then name, so every process renders the same config and hosts block.
+fn routable_containers(containers: &HashMap<String, ContainerInfo>) -> Vec<ContainerInfo> {
+    let mut running_containers: Vec<ContainerInfo> = containers.values()
+        .filter(|c| c.is_running)
+        .cloned()
+        .collect();
+    running_containers.sort_by(|a, b| (&a.domain, &a.name).cmp(&(&b.domain, &b.name)));
+
+    for container in running_containers.iter_mut() {
+        for port in drop_http_ports_overlapping_ssl(container) {
+            warn!("Container {} lists port {} in both ports and sslPorts, serving it over SSL only", container.name, port);
+        }
+    }
+
+    running_containers
+}
+
+/// Update configuration based on active containers
+async fn update_configuration(docker: &Docker, containers: &HashMap<String, ContainerInfo>) -> Result<()> {
+    info!("Updating configuration with {} containers", containers.len());
+
+    let mut running_containers = routable_containers(containers);
+    let mut warnings = Vec::new();
+    let RoutingPlan { domains, external_ports, ssl_domains, alias_domains } = plan_routing(&running_containers)?;
+
+    let hosts_entries: Vec<String> = domains.iter().chain(&alias_domains).cloned().collect();
+
+    if crate::config::get().watch_verbose {
+        log_routing_changes(RoutingSnapshot {
+            domains: hosts_entries.iter().cloned().collect(),
+            ports: external_ports.iter().copied().collect(),
+        });
+    }
+
+    // Generate SSL certificates
+    for domain in &ssl_domains {
+        let cert_gen = CertificateGenerator::new(domain).with_extra_ips(domain_extra_ips(&running_containers, domain));
+        if let Err(e) = cert_gen.generate_certificates().await {
+            record_warning(&mut warnings, format!("Failed to generate SSL certificate for {}: {}", domain, e));
+        }
+    }
+
+    // Copy client CAs next to the certificates, which are mounted into the NGINX container.
+    // Without its CA file NGINX would refuse to start, so a failed copy disables mTLS for the site.
+    for container in running_containers.iter_mut().filter(|c| !c.ssl_ports.is_empty()) {
+        if let Some(client_ca) = &container.client_ca {
+            let cert_gen = CertificateGenerator::new(&container.domain);
+            if let Err(e) = cert_gen.install_client_ca(client_ca).await {
+                record_warning(
+                    &mut warnings,
+                    format!("Failed to install client CA for {}, not requiring client certificates: {}", container.domain, e),
+                );
+                container.client_ca = None;
             }
         }
     }
 
-    // Update hosts file
-    let hosts_manager = HostsFileManager::new(None);
-    if let Err(e) = hosts_manager.update_managed_block(&domains).await {
-        warn!("Failed to update hosts file: {}", e);
+    // Update hosts file, unless DNS is managed outside autolocalhost
+    if crate::config::get().manage_hosts {
+        let hosts_manager = HostsFileManager::new(None);
+        match hosts_manager.update_managed_block(&hosts_entries).await {
+            Ok(()) => verify_hosts_entries(&domains).await,
+            Err(e) => record_warning(&mut warnings, format!("Failed to update hosts file: {}", e)),
+        }
+    } else {
+        debug!("Hosts file management is disabled, not touching the hosts file");
     }
 
     // Generate NGINX config
     let config_generator = ConfigGenerator::new(&running_containers);
-    let nginx_config_path = crate::installer::get_data_dir().join("nginx.conf");
-    if let Err(e) = config_generator.generate_config(nginx_config_path.to_str().unwrap()).await {
-        warn!("Failed to generate NGINX config: {}", e);
+    let nginx_config_path = crate::installer::get_nginx_config_dir().join("nginx.conf");
+    if let Err(e) = config_generator.generate_config(&nginx_config_path).await {
+        record_warning(&mut warnings, format!("Failed to generate NGINX config: {}", e));
     }
 
     // Convert HashSet to Vec for NGINX container manager
     let ports: Vec<u16> = external_ports.into_iter().collect();
 
-    // Start NGINX container
+    // Reload NGINX, or recreate its container when the published ports changed
     let nginx_manager = ContainerManager::new(docker.clone());
-    if let Err(e) = nginx_manager.create_and_start(&ports).await {
-        warn!("Failed to manage NGINX container: {}", e);
+    match nginx_manager.apply(&ports).await {
+        Ok(strategy) => debug!("NGINX configuration applied ({:?})", strategy),
+        Err(e) => record_warning(&mut warnings, format!("Failed to manage NGINX container: {}", e)),
+    }
+
+    // Containers attached to the network by hand are reachable from NGINX but never routed
+    if !crate::config::get().use_host_network {
+        match nginx_manager.list_containers_in_network().await {
+            Ok(names) => {
+                let unmanaged = names.iter().filter(|name| {
+                    name.as_str() != nginx_manager.container_name() && !containers.values().any(|c| &c.name == *name)
+                });
+                for name in unmanaged {
+                    record_warning(&mut warnings, format!(
+                        "Container {} joined network {} without autolocalhost labels and is not routed",
+                        name,
+                        nginx_manager.network_name()
+                    ));
+                }
+            }
+            Err(e) => debug!("Could not list containers in network {}: {}", nginx_manager.network_name(), e),
+        }
+    }
+
+    // Summary for external tools and the status command
+    let state = ServiceState::new(&running_containers, hosts_entries, ports, warnings);
+    if let Err(e) = crate::state::write(&state).await {
+        warn!("Failed to write {}: {}", crate::state::get_state_file_path().display(), e);
     }
 
     info!("Configuration updated successfully");
     Ok(())
 }
+
+/// Log a warning and keep it for the state file
+fn record_warning(warnings: &mut Vec<String>, message: String) {
+    warn!("{}", message);
+    warnings.push(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::port_mapping::PortMapping;
+
+    #[test]
+    fn plans_routing_for_ssl_only_container() {
+        let containers = vec![ContainerInfo {
+            id: String::from("secure-id"),
+            name: String::from("secure"),
+            is_running: true,
+            domain: String::from("secure.test"),
+            location_path: String::from("/"),
+            ssl_ports: vec![PortMapping::new(443, 8443), PortMapping::new(8443, 8443)],
+            ..Default::default()
+        }];
+
+        let plan = plan_routing(&containers).unwrap();
+
+        assert_eq!(plan.domains, vec![String::from("secure.test")]);
+        assert_eq!(plan.external_ports, HashSet::from([443, 8443]));
+        assert_eq!(plan.ssl_domains, vec![String::from("secure.test")]);
+    }
+
+    #[test]
+    fn plans_routing_for_containers_sharing_a_domain() {
+        let container = |name: &str, path: &str| ContainerInfo {
+            name: String::from(name),
+            is_running: true,
+            domain: String::from("app.test"),
+            location_path: String::from(path),
+            ssl_ports: vec![PortMapping::new(443, 8080)],
+            ..Default::default()
+        };
+
+        let plan = plan_routing(&[container("web", "/"), container("api", "/api/")]).unwrap();
+        assert_eq!(plan.domains, vec![String::from("app.test")]);
+        assert_eq!(plan.ssl_domains, vec![String::from("app.test")]);
+
+        let err = plan_routing(&[container("web", "/"), container("other", "/")]).unwrap_err();
+        assert!(err.to_string().contains("Duplicate route app.test:443/ in container other"));
+    }
+
+    #[test]
+    fn lists_added_and_removed_domains_and_ports() {
+        let previous = RoutingSnapshot {
+            domains: BTreeSet::from([String::from("a.test"), String::from("b.test")]),
+            ports: BTreeSet::from([80, 443]),
+        };
+        let current = RoutingSnapshot {
+            domains: BTreeSet::from([String::from("b.test"), String::from("c.test")]),
+            ports: BTreeSet::from([80, 8080]),
+        };
+
+        assert_eq!(
+            current.changes_since(&previous),
+            vec!["+ domain c.test", "- domain a.test", "+ port 8080", "- port 443"]
+        );
+        assert!(current.changes_since(&current).is_empty());
+    }
+
+    #[test]
+    fn orders_routable_containers_by_domain_then_name() {
+        let containers: HashMap<String, ContainerInfo> = [("web", "b.test"), ("api", "a.test"), ("admin", "b.test"), ("old", "c.test")]
+            .into_iter()
+            .map(|(name, domain)| {
+                let container = ContainerInfo {
+                    id: format!("{}-id", name),
+                    name: name.to_string(),
+                    is_running: name != "old",
+                    domain: domain.to_string(),
+                    ..Default::default()
+                };
+                (container.id.clone(), container)
+            })
+            .collect();
+
+        let names: Vec<String> = routable_containers(&containers).into_iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["api", "admin", "web"]);
+    }
+
+    #[test]
+    fn prefers_ssl_for_ports_listed_twice() {
+        let mut container = ContainerInfo {
+            name: String::from("web"),
+            ports: vec![PortMapping::new(80, 8080), PortMapping::new(443, 8080)],
+            ssl_ports: vec![PortMapping::new(443, 8080)],
+            ..Default::default()
+        };
+
+        assert_eq!(drop_http_ports_overlapping_ssl(&mut container), vec![443]);
+        assert_eq!(container.ports.len(), 1);
+        assert_eq!(container.ports[0].external, 80);
+        assert_eq!(container.ssl_ports.len(), 1);
+    }
+}
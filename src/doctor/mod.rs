@@ -0,0 +1,105 @@
+use anyhow::Result;
+use bollard::Docker;
+use log::warn;
+use std::path::{Path, PathBuf};
+
+/// Host paths Docker Desktop shares with its VM by default. Bind mounts from anywhere else
+/// show up as empty directories inside the container. `None` where every path is shared
+/// (the WSL 2 backend on Windows).
+fn desktop_shared_roots() -> Option<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        Some(&["/Users", "/Volumes", "/private", "/tmp", "/var/folders"])
+    } else if cfg!(target_os = "linux") {
+        Some(&["/home"])
+    } else {
+        None
+    }
+}
+
+/// Run the environment checks and print their results
+pub async fn run() -> Result<()> {
+    let docker = match crate::docker::try_connect_docker().await {
+        Ok(docker) => docker,
+        Err(e) => {
+            println!("[fail] Docker: unavailable ({})", e);
+            return Ok(());
+        }
+    };
+
+    let desktop = is_docker_desktop(&docker).await;
+    println!("[ ok ] Docker: connected{}", if desktop { " (Docker Desktop)" } else { "" });
+
+    if !desktop {
+        println!("[ ok ] Mounted directories: Docker runs on this host, every path can be mounted");
+        return Ok(());
+    }
+
+    let unshared = unshared_dirs(&crate::installer::get_mounted_dirs());
+    if unshared.is_empty() {
+        println!("[ ok ] Mounted directories: all under paths Docker Desktop shares by default");
+    } else {
+        println!("[warn] Mounted directories outside the paths Docker Desktop shares by default:");
+        for dir in &unshared {
+            println!("         {}", dir.display());
+        }
+        println!("       NGINX sees them as empty directories. Set AUTOLOCALHOST_MOUNT_BASE to a shared");
+        println!("       directory, or add the paths under Settings > Resources > File sharing.");
+    }
+
+    Ok(())
+}
+
+/// Warn at startup when Docker Desktop is unlikely to see the directories mounted into NGINX
+pub async fn warn_if_mounts_unshared(docker: &Docker) {
+    if !is_docker_desktop(docker).await {
+        return;
+    }
+
+    for dir in unshared_dirs(&crate::installer::get_mounted_dirs()) {
+        warn!(
+            "{} is outside the paths Docker Desktop shares by default, NGINX may see it empty; \
+             set AUTOLOCALHOST_MOUNT_BASE to a shared directory or share the path in Docker Desktop",
+            dir.display()
+        );
+    }
+}
+
+async fn is_docker_desktop(docker: &Docker) -> bool {
+    match docker.info().await {
+        Ok(info) => info.operating_system.map(|os| os.contains("Docker Desktop")).unwrap_or(false),
+        Err(e) => {
+            warn!("Failed to query Docker system info: {}", e);
+            false
+        }
+    }
+}
+
+fn unshared_dirs(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let Some(roots) = desktop_shared_roots() else {
+        return Vec::new();
+    };
+
+    dirs.iter()
+        .filter(|dir| !is_under_any(dir, roots))
+        .cloned()
+        .collect()
+}
+
+/// Whether a directory is under one of the roots, following symlinks such as macOS `/var` -> `/private/var`
+fn is_under_any(dir: &Path, roots: &[&str]) -> bool {
+    let resolved = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    roots.iter().any(|root| resolved.starts_with(root) || dir.starts_with(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_shared_roots_by_path_component() {
+        let roots = ["/Users", "/private"];
+        assert!(is_under_any(Path::new("/Users/dev/autolocalhost/certs"), &roots));
+        assert!(!is_under_any(Path::new("/Usersdata/certs"), &roots));
+        assert!(!is_under_any(Path::new("/opt/autolocalhost/certs"), &roots));
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Result, anyhow};
 use log::{info, warn, debug};
+use chrono::Utc;
 use regex::Regex;
 use std::env;
 use std::path::{Path, PathBuf};
@@ -10,22 +11,26 @@ pub struct HostsFileManager {
     hosts_file_path: PathBuf,
     block_start: String,
     block_end: String,
+    /// Write new content with the predominant line ending of the existing file (CRLF on Windows)
+    preserve_line_endings: bool,
 }
 
 impl HostsFileManager {
     /// Create a new HostsFileManager
     pub fn new(hosts_file_path: Option<PathBuf>) -> Self {
-        let hosts_file_path = hosts_file_path.unwrap_or_else(|| Self::get_system_hosts_file_path());
+        let hosts_file_path = hosts_file_path.unwrap_or_else(Self::get_system_hosts_file_path);
 
         Self {
             hosts_file_path,
-            block_start: String::from("# BEGIN MANAGED BLOCK - DO NOT EDIT MANUALLY # kz.byte0.autolocalhost"),
-            block_end: String::from("# END MANAGED BLOCK - DO NOT EDIT MANUALLY # kz.byte0.autolocalhost"),
+            // Instances with different label prefixes keep separate blocks
+            block_start: format!("# BEGIN MANAGED BLOCK - DO NOT EDIT MANUALLY # {}", crate::config::get().label_prefix),
+            block_end: format!("# END MANAGED BLOCK - DO NOT EDIT MANUALLY # {}", crate::config::get().label_prefix),
+            preserve_line_endings: cfg!(windows),
         }
     }
 
     /// Get the path to the system hosts file
-    fn get_system_hosts_file_path() -> PathBuf {
+    pub fn get_system_hosts_file_path() -> PathBuf {
         if cfg!(windows) {
             let system_root = env::var("SYSTEMROOT").unwrap_or_else(|_| String::from("C:\\Windows"));
             Path::new(&system_root)
@@ -38,13 +43,27 @@ impl HostsFileManager {
         }
     }
 
+    /// Path of the hosts file this manager edits
+    pub fn hosts_file_path(&self) -> &Path {
+        &self.hosts_file_path
+    }
+
+    /// Current hosts file content and the content `update_managed_block` would write, without
+    /// writing it. A block that only differs in its generation timestamp counts as unchanged.
+    pub async fn preview_managed_block(&self, domains: &[String]) -> Result<(String, String)> {
+        let content = fs::read_to_string(&self.hosts_file_path).await
+            .map_err(|e| anyhow!("Failed to read hosts file: {}", e))?;
+        let updated = self.updated_content(&content, &without_localhost(domains));
+
+        if without_generation_stamp(&updated) == without_generation_stamp(&content) {
+            return Ok((content.clone(), content));
+        }
+        Ok((content, updated))
+    }
+
     /// Update the managed block in the hosts file
     pub async fn update_managed_block(&self, domains: &[String]) -> Result<()> {
-        // Filter out "localhost" from domains
-        let domains: Vec<String> = domains.iter()
-        .filter(|domain| *domain != "localhost")
-        .cloned()
-        .collect();
+        let domains = without_localhost(domains);
 
         debug!("Updating hosts file at {}", self.hosts_file_path.display());
 
@@ -55,7 +74,7 @@ impl HostsFileManager {
         };
 
         // Update the content
-        let updated_content = self.update_block_in_content(&content, &domains);
+        let updated_content = self.updated_content(&content, &domains);
 
         // Write the updated content back to the file
         match fs::write(&self.hosts_file_path, updated_content).await {
@@ -70,13 +89,26 @@ impl HostsFileManager {
         }
     }
 
+    /// New hosts file content, with line endings matching the existing file when preserving them
+    fn updated_content(&self, content: &str, domains: &[String]) -> String {
+        // Work on LF only so the block pattern also matches around CRLF line breaks
+        let updated = self.update_block_in_content(&content.replace("\r\n", "\n"), domains);
+
+        if self.preserve_line_endings && uses_crlf(content) {
+            updated.replace('\n', "\r\n")
+        } else {
+            updated
+        }
+    }
+
     /// Update or create the managed block in the hosts file content
     fn update_block_in_content(&self, content: &str, domains: &[String]) -> String {
-        // Pattern to find the block including possible empty lines before and after
+        // Pattern to find the block including possible empty lines before and after. The markers
+        // must fill their whole line, so a prefix never matches the block of a longer prefix.
         let block_pattern = format!(
-            r"\n*{}[\s\S]*?{}\n*",
+            r"(?m)\n*^{}$[\s\S]*?^{}$\n*",
             regex::escape(&self.block_start),
-                                    regex::escape(&self.block_end)
+            regex::escape(&self.block_end)
         );
 
         let re = Regex::new(&block_pattern).unwrap();
@@ -120,6 +152,13 @@ impl HostsFileManager {
     fn create_managed_block(&self, domains: &[String]) -> String {
         let mut block = format!("{}\n", self.block_start);
 
+        // Record which version wrote the block and when; the markers stay stable for replacement
+        block.push_str(&format!(
+            "# Generated by autolocalhost {} at {}\n",
+            env!("CARGO_PKG_VERSION"),
+            Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+
         for domain in domains {
             block.push_str(&format!("127.0.0.1 {}\n", domain));
         }
@@ -164,3 +203,84 @@ impl HostsFileManager {
         }
     }
 }
+
+/// Domains to write, without "localhost" which the hosts file already maps
+fn without_localhost(domains: &[String]) -> Vec<String> {
+    domains.iter()
+        .filter(|domain| *domain != "localhost")
+        .cloned()
+        .collect()
+}
+
+/// Content without the "# Generated by ..." line of the managed block
+fn without_generation_stamp(content: &str) -> String {
+    content.lines()
+        .filter(|line| !line.starts_with("# Generated by autolocalhost "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Whether most line breaks in the content are CRLF rather than bare LF
+fn uses_crlf(content: &str) -> bool {
+    let crlf = content.matches("\r\n").count();
+    let lf = content.matches('\n').count() - crlf;
+    crlf > lf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> HostsFileManager {
+        HostsFileManager {
+            preserve_line_endings: true,
+            ..HostsFileManager::new(Some(PathBuf::from("hosts")))
+        }
+    }
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let content = "127.0.0.1 localhost\r\n::1 localhost\r\n";
+        let updated = manager().updated_content(content, &[String::from("app.test")]);
+
+        assert!(updated.contains("127.0.0.1 app.test\r\n"));
+        assert_eq!(updated.matches('\n').count(), updated.matches("\r\n").count());
+
+        // Replacing the existing block keeps the entries and the CRLF line endings
+        let rewritten = manager().updated_content(&updated, &[String::from("other.test")]);
+        assert!(rewritten.starts_with("127.0.0.1 localhost\r\n"));
+        assert!(rewritten.contains("127.0.0.1 other.test\r\n"));
+        assert!(!rewritten.contains("app.test"));
+        assert_eq!(rewritten.matches('\n').count(), rewritten.matches("\r\n").count());
+    }
+
+    #[test]
+    fn leaves_blocks_of_longer_label_prefixes_alone() {
+        let dev = HostsFileManager {
+            block_start: format!("{}.dev", manager().block_start),
+            block_end: format!("{}.dev", manager().block_end),
+            ..manager()
+        };
+        let content = dev.updated_content("127.0.0.1 localhost\n", &[String::from("dev.test")]);
+
+        let updated = manager().updated_content(&content, &[String::from("app.test")]);
+        assert!(updated.contains("127.0.0.1 dev.test\n"));
+        assert!(updated.contains("127.0.0.1 app.test\n"));
+
+        let removed = manager().updated_content(&updated, &[]);
+        assert!(removed.contains("127.0.0.1 dev.test\n"));
+        assert!(!removed.contains("app.test"));
+    }
+
+    #[test]
+    fn normalizes_mixed_line_endings_to_the_predominant_style() {
+        let mostly_lf = "127.0.0.1 localhost\n::1 localhost\r\n10.0.0.1 nas\n";
+        let updated = manager().updated_content(mostly_lf, &[String::from("app.test")]);
+        assert!(!updated.contains('\r'));
+
+        let mostly_crlf = "127.0.0.1 localhost\r\n::1 localhost\n10.0.0.1 nas\r\n";
+        let updated = manager().updated_content(mostly_crlf, &[String::from("app.test")]);
+        assert_eq!(updated.matches('\n').count(), updated.matches("\r\n").count());
+        assert!(!updated.contains("\r\r"));
+    }
+}
@@ -2,14 +2,18 @@ use anyhow::{bail, Context, Result};
 use log::{error, info, warn};
 use std::env;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs;
+use tokio::time::timeout;
 
 #[cfg(unix)]
 mod unix;
 #[cfg(windows)]
 mod windows;
 
-pub async fn install() -> Result<()> {
+/// Install and start the service. Unless `keep_root` is set, the unix service runs as a
+/// dedicated system user instead of root.
+pub async fn install(keep_root: bool) -> Result<()> {
     info!("Starting autolocalhost installation...");
 
     // Check privileges
@@ -29,8 +33,20 @@ pub async fn install() -> Result<()> {
         stop_service().await?;
     }
 
+    // Create the service user before the directories it will own
+    if !keep_root {
+        create_service_user().await?;
+    }
+
     // Create directories
-    create_directories().await?;
+    validate_dir_overrides().await?;
+    create_directories(keep_root).await?;
+
+    // Warn about host security policies that may block the bind mounts
+    check_security_policies();
+
+    // Keep antivirus from scanning certificate files on every access
+    add_av_exclusions().await;
 
     // Copy executable
     copy_executable(&current_exe).await?;
@@ -39,7 +55,10 @@ pub async fn install() -> Result<()> {
     copy_nginx_template().await?;
 
     // Install service
-    install_service().await?;
+    install_service(keep_root).await?;
+
+    // Make the service's log visible in the platform's event viewer
+    register_event_source().await;
 
     // Enable autostart
     enable_autostart().await?;
@@ -50,14 +69,24 @@ pub async fn install() -> Result<()> {
     info!("Autolocalhost installation completed successfully!");
     info!("The service has been started and will start automatically on system boot");
 
+    match service_status().await {
+        Ok(status) => info!("Service status:\n{}", status),
+        Err(e) => warn!("Failed to query service status: {}", e),
+    }
+
     Ok(())
 }
 
-pub async fn uninstall() -> Result<()> {
+pub async fn uninstall(docker_timeout: Duration) -> Result<()> {
     info!("Starting autolocalhost uninstallation...");
 
-    // Clean up nginx container first
-    cleanup_nginx_container().await;
+    // Clean up nginx container first, giving up if Docker doesn't respond in time
+    if timeout(docker_timeout, cleanup_nginx_container()).await.is_err() {
+        warn!(
+            "Docker did not respond within {} seconds, skipping nginx container cleanup",
+            docker_timeout.as_secs()
+        );
+    }
 
     // Stop and remove service
     if is_service_running().await? {
@@ -67,6 +96,10 @@ pub async fn uninstall() -> Result<()> {
 
     uninstall_service().await?;
 
+    remove_av_exclusions().await;
+
+    unregister_event_source().await;
+
     // Remove executable (best effort)
     let install_path = get_install_dir().join(get_executable_name());
     if install_path.exists() {
@@ -88,9 +121,11 @@ pub async fn uninstall() -> Result<()> {
     Ok(())
 }
 
-async fn create_directories() -> Result<()> {
+async fn create_directories(keep_root: bool) -> Result<()> {
     let config_dir = get_config_dir();
     let data_dir = get_data_dir();
+    let nginx_config_dir = get_nginx_config_dir();
+    let nginx_cache_dir = get_nginx_cache_dir();
     let certs_dir = get_certs_dir();
     let ca_dir = get_ca_dir();
     let log_dir = get_log_dir();
@@ -118,6 +153,22 @@ async fn create_directories() -> Result<()> {
         .with_context(|| format!("Failed to create data directory: {}", data_dir.display()))?;
     info!("Created data directory: {}", certs_dir.display());
 
+    fs::create_dir_all(&nginx_config_dir).await.with_context(|| {
+        format!(
+            "Failed to create nginx config directory: {}",
+            nginx_config_dir.display()
+        )
+    })?;
+    info!("Created nginx config directory: {}", nginx_config_dir.display());
+
+    fs::create_dir_all(&nginx_cache_dir).await.with_context(|| {
+        format!(
+            "Failed to create nginx cache directory: {}",
+            nginx_cache_dir.display()
+        )
+    })?;
+    info!("Created nginx cache directory: {}", nginx_cache_dir.display());
+
     fs::create_dir_all(&certs_dir)
         .await
         .with_context(|| format!("Failed to create certs directory: {}", certs_dir.display()))?;
@@ -141,6 +192,10 @@ async fn create_directories() -> Result<()> {
     })?;
     info!("Created nginx log directory: {}", nginx_log_dir.display());
 
+    if !keep_root {
+        set_directory_owner(&service_owned_dirs())?;
+    }
+
     Ok(())
 }
 
@@ -180,7 +235,7 @@ async fn cleanup_nginx_container() {
     info!("Cleaning up managed nginx container...");
 
     // Try to connect to Docker
-    let docker = match try_connect_docker().await {
+    let docker = match crate::docker::try_connect_docker().await {
         Ok(client) => client,
         Err(e) => {
             warn!("Failed to connect to Docker, skipping container cleanup: {}", e);
@@ -204,24 +259,6 @@ async fn cleanup_nginx_container() {
     }
 }
 
-async fn try_connect_docker() -> Result<bollard::Docker> {
-    use bollard::Docker;
-
-    let docker = if cfg!(windows) {
-        Docker::connect_with_http_defaults()
-            .context("Failed to connect to Docker over HTTP")?
-    } else {
-        Docker::connect_with_socket_defaults()
-            .context("Failed to connect to Docker socket")?
-    };
-
-    // Test the connection
-    docker.version().await
-        .context("Docker connection test failed")?;
-
-    Ok(docker)
-}
-
 pub fn get_install_dir() -> PathBuf {
     if cfg!(windows) {
         PathBuf::from(r"C:\Program Files\Autolocalhost")
@@ -248,12 +285,78 @@ pub fn get_data_dir() -> PathBuf {
     }
 }
 
+/// Directory holding the generated nginx.conf. It is bind-mounted as a directory
+/// (not as a single file) so atomic renames inside it are visible to the container.
+pub fn get_nginx_config_dir() -> PathBuf {
+    mount_base().unwrap_or_else(get_data_dir).join("nginx")
+}
+
+/// Directory for the proxy_cache zones of the cache label, mounted read-write into NGINX
+pub fn get_nginx_cache_dir() -> PathBuf {
+    mount_base().unwrap_or_else(get_data_dir).join("nginx-cache")
+}
+
+/// Environment variables that move the certificate directories out of the data directory
+const CERTS_DIR_ENV: &str = "AUTOLOCALHOST_CERTS_DIR";
+const CA_DIR_ENV: &str = "AUTOLOCALHOST_CA_DIR";
+/// Environment variable that moves every directory mounted into the NGINX container under one
+/// base, e.g. a folder Docker Desktop shares with its VM
+const MOUNT_BASE_ENV: &str = "AUTOLOCALHOST_MOUNT_BASE";
+
+fn mount_base() -> Option<PathBuf> {
+    dir_override(MOUNT_BASE_ENV)
+}
+
 pub fn get_certs_dir() -> PathBuf {
-    return get_data_dir().join("certs");
+    dir_override(CERTS_DIR_ENV)
+        .or_else(|| mount_base().map(|base| base.join("certs")))
+        .unwrap_or_else(|| get_data_dir().join("certs"))
 }
 
 pub fn get_ca_dir() -> PathBuf {
-    return get_data_dir().join("ca");
+    dir_override(CA_DIR_ENV).unwrap_or_else(|| get_data_dir().join("ca"))
+}
+
+fn dir_override(name: &str) -> Option<PathBuf> {
+    env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+/// Directories handed to the service user on install. The install directory is shared with
+/// other binaries and stays root-owned.
+fn service_owned_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![get_config_dir(), get_data_dir(), get_log_dir()];
+    dirs.extend(dir_overrides().into_iter().map(|(_, dir)| dir));
+    dirs
+}
+
+/// Hand files that a command run with sudo wrote into the service directories back to the
+/// service user, so the service can keep rewriting them. Does nothing for a root service.
+pub fn restore_service_ownership() {
+    #[cfg(unix)]
+    unix::restore_service_ownership(&service_owned_dirs());
+}
+
+/// Directory overrides that are set, as (environment variable, directory) pairs
+pub fn dir_overrides() -> Vec<(&'static str, PathBuf)> {
+    [CERTS_DIR_ENV, CA_DIR_ENV, MOUNT_BASE_ENV]
+        .into_iter()
+        .filter_map(|name| dir_override(name).map(|dir| (name, dir)))
+        .collect()
+}
+
+/// Check that overridden directories are absolute and can be created
+pub async fn validate_dir_overrides() -> Result<()> {
+    for (name, dir) in dir_overrides() {
+        if !dir.is_absolute() {
+            bail!("{} must be an absolute path, got {}", name, dir.display());
+        }
+
+        fs::create_dir_all(&dir)
+            .await
+            .with_context(|| format!("Cannot create {} directory {}", name, dir.display()))?;
+    }
+
+    Ok(())
 }
 
 pub fn get_log_dir() -> PathBuf {
@@ -267,7 +370,15 @@ pub fn get_log_dir() -> PathBuf {
 }
 
 pub fn get_nginx_log_dir() -> PathBuf {
-    return get_log_dir().join("nginx");
+    match mount_base() {
+        Some(base) => base.join("nginx-log"),
+        None => get_log_dir().join("nginx"),
+    }
+}
+
+/// Host directories bind-mounted into the NGINX container
+pub fn get_mounted_dirs() -> Vec<PathBuf> {
+    vec![get_certs_dir(), get_nginx_config_dir(), get_nginx_log_dir(), get_nginx_cache_dir()]
 }
 
 fn get_executable_name() -> &'static str {
@@ -280,7 +391,7 @@ fn get_executable_name() -> &'static str {
 
 // Platform-specific implementations
 #[cfg(unix)]
-async fn is_service_running() -> Result<bool> {
+pub async fn is_service_running() -> Result<bool> {
     unix::is_service_running().await
 }
 
@@ -290,8 +401,8 @@ async fn stop_service() -> Result<()> {
 }
 
 #[cfg(unix)]
-async fn install_service() -> Result<()> {
-    unix::install_service().await
+async fn install_service(keep_root: bool) -> Result<()> {
+    unix::install_service(keep_root).await
 }
 
 #[cfg(unix)]
@@ -310,7 +421,7 @@ async fn start_service() -> Result<()> {
 }
 
 #[cfg(windows)]
-async fn is_service_running() -> Result<bool> {
+pub async fn is_service_running() -> Result<bool> {
     windows::is_service_running().await
 }
 
@@ -320,7 +431,7 @@ async fn stop_service() -> Result<()> {
 }
 
 #[cfg(windows)]
-async fn install_service() -> Result<()> {
+async fn install_service(_keep_root: bool) -> Result<()> {
     windows::install_service().await
 }
 
@@ -339,6 +450,108 @@ async fn start_service() -> Result<()> {
     windows::start_service().await
 }
 
+#[cfg(unix)]
+async fn service_status() -> Result<String> {
+    unix::service_status().await
+}
+
+#[cfg(windows)]
+async fn service_status() -> Result<String> {
+    windows::service_status().await
+}
+
+// Platform-specific security policy checks
+#[cfg(unix)]
+fn check_security_policies() {
+    unix::warn_if_selinux_enforcing()
+}
+
+#[cfg(windows)]
+fn check_security_policies() {}
+
+// Platform-specific antivirus exclusions
+#[cfg(unix)]
+async fn add_av_exclusions() {}
+
+#[cfg(windows)]
+async fn add_av_exclusions() {
+    for path in [get_certs_dir(), get_data_dir()] {
+        if let Err(e) = windows::add_defender_exclusion(&path).await {
+            warn!("Failed to add Windows Defender exclusion for {}: {}", path.display(), e);
+            warn!("Defender may be managed by Group Policy; certificate reads may be slower");
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn remove_av_exclusions() {}
+
+#[cfg(windows)]
+async fn remove_av_exclusions() {
+    for path in [get_certs_dir(), get_data_dir()] {
+        if let Err(e) = windows::remove_defender_exclusion(&path).await {
+            warn!("Failed to remove Windows Defender exclusion for {}: {}", path.display(), e);
+        }
+    }
+}
+
+// Platform-specific event log registration; the unix service logs to the journal
+#[cfg(unix)]
+async fn register_event_source() {}
+
+#[cfg(windows)]
+async fn register_event_source() {
+    if let Err(e) = windows::register_event_source().await {
+        warn!("Failed to register the event log source, service logs won't appear in Event Viewer: {}", e);
+    }
+}
+
+#[cfg(unix)]
+async fn unregister_event_source() {}
+
+#[cfg(windows)]
+async fn unregister_event_source() {
+    if let Err(e) = windows::unregister_event_source().await {
+        warn!("Failed to remove the event log source: {}", e);
+    }
+}
+
+/// Install the logger for the running service
+#[cfg(unix)]
+pub fn init_service_logger(logger: env_logger::Logger) -> Result<()> {
+    let max_level = logger.filter();
+    log::set_boxed_logger(Box::new(logger))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Install the logger for the running service, also reporting to the Windows event log
+#[cfg(windows)]
+pub fn init_service_logger(logger: env_logger::Logger) -> Result<()> {
+    windows::init_service_logger(logger)
+}
+
+// Platform-specific service account; the Windows service keeps running as LocalSystem
+#[cfg(unix)]
+async fn create_service_user() -> Result<()> {
+    unix::create_service_user().await
+}
+
+#[cfg(windows)]
+async fn create_service_user() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_directory_owner(dirs: &[PathBuf]) -> Result<()> {
+    unix::set_directory_owner(dirs)
+}
+
+#[cfg(windows)]
+fn set_directory_owner(_dirs: &[PathBuf]) -> Result<()> {
+    Ok(())
+}
+
 // Platform-specific privilege checking
 #[cfg(unix)]
 fn check_privileges() -> Result<()> {
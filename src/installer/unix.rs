@@ -1,10 +1,26 @@
-use anyhow::{Result, Context, bail};
-use log::{info, warn};
+use anyhow::{Result, Context, anyhow, bail};
+use log::{debug, error, info, warn};
+use nix::unistd::User;
+use std::os::unix::fs::{lchown, MetadataExt};
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::process::Command as AsyncCommand;
 use nix::libc;
 
+const SELINUX_ENFORCE_PATH: &str = "/sys/fs/selinux/enforce";
+
+/// SELinux mode of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelinuxState {
+    Enforcing,
+    Permissive,
+    Disabled,
+}
+
 const SERVICE_NAME: &str = "autolocalhost";
+/// Unprivileged account the service runs as unless installed with --keep-root
+const SERVICE_USER: &str = "autolocalhost";
+const HOSTS_FILE_PATH: &str = "/etc/hosts";
 const SERVICE_FILE_CONTENT: &str = r#"[Unit]
 Description=Autolocalhost - Local development environment automation
 After=network.target docker.service
@@ -35,6 +51,34 @@ ProtectControlGroups=yes
 WantedBy=multi-user.target
 "#;
 
+/// Unit file content. Directory overrides set during installation are passed on to the
+/// service and made writable despite ProtectSystem=strict.
+fn service_file_content(keep_root: bool) -> String {
+    let content = if keep_root {
+        String::from(SERVICE_FILE_CONTENT)
+    } else {
+        SERVICE_FILE_CONTENT.replace("User=root\n", &format!("User={}\n", SERVICE_USER))
+    };
+
+    let overrides = crate::installer::dir_overrides();
+    if overrides.is_empty() {
+        return content;
+    }
+
+    let environment: String = overrides
+        .iter()
+        .map(|(name, dir)| format!("Environment=\"{}={}\"\n", name, dir.display()))
+        .collect();
+    let extra_paths: String = overrides
+        .iter()
+        .map(|(_, dir)| format!(" \"{}\"", dir.display()))
+        .collect();
+
+    content
+        .replace("SyslogIdentifier=autolocalhost\n", &format!("SyslogIdentifier=autolocalhost\n{}", environment))
+        .replace("/etc/autolocalhost\n", &format!("/etc/autolocalhost{}\n", extra_paths))
+}
+
 pub async fn is_service_running() -> Result<bool> {
     let output = AsyncCommand::new("systemctl")
     .args(["is-active", "--quiet", SERVICE_NAME])
@@ -62,11 +106,11 @@ pub async fn stop_service() -> Result<()> {
     Ok(())
 }
 
-pub async fn install_service() -> Result<()> {
+pub async fn install_service(keep_root: bool) -> Result<()> {
     let service_path = format!("/etc/systemd/system/{}.service", SERVICE_NAME);
 
     // Write service file
-    fs::write(&service_path, SERVICE_FILE_CONTENT).await
+    fs::write(&service_path, service_file_content(keep_root)).await
     .with_context(|| format!("Failed to write service file: {}", service_path))?;
 
     info!("Created systemd service file: {}", service_path);
@@ -83,9 +127,45 @@ pub async fn install_service() -> Result<()> {
     }
 
     info!("Reloaded systemd daemon");
+
+    verify_service_installation().await?;
+    Ok(())
+}
+
+/// Check that systemd picked up the unit file we wrote, logging its effective content
+pub async fn verify_service_installation() -> Result<()> {
+    let output = AsyncCommand::new("systemctl")
+    .args(["cat", SERVICE_NAME])
+    .output()
+    .await
+    .context("Failed to run systemctl cat")?;
+
+    if output.status.success() {
+        debug!("Effective systemd unit:\n{}", String::from_utf8_lossy(&output.stdout).trim_end());
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!(
+            "systemd does not see the {} unit after daemon-reload: {}",
+            SERVICE_NAME,
+            stderr.trim()
+        );
+    }
+
     Ok(())
 }
 
+/// Human-readable service status from `systemctl status`
+pub async fn service_status() -> Result<String> {
+    // Exits non-zero for inactive services, but still prints the status
+    let output = AsyncCommand::new("systemctl")
+    .args(["status", SERVICE_NAME, "--no-pager"])
+    .output()
+    .await
+    .context("Failed to run systemctl status")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
 pub async fn uninstall_service() -> Result<()> {
     // Disable service
     let _ = AsyncCommand::new("systemctl")
@@ -143,6 +223,121 @@ pub async fn start_service() -> Result<()> {
     Ok(())
 }
 
+/// Create the system user the service runs as and let it talk to Docker and edit the hosts file
+pub async fn create_service_user() -> Result<()> {
+    if service_user()?.is_some() {
+        info!("Service user {} already exists", SERVICE_USER);
+    } else {
+        let data_dir = crate::installer::get_data_dir();
+        let output = AsyncCommand::new("useradd")
+        .args(["-r", "-s", "/bin/false", "-d"])
+        .arg(&data_dir)
+        .arg(SERVICE_USER)
+        .output()
+        .await
+        .context("Failed to run useradd")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to create service user {}: {}", SERVICE_USER, stderr.trim());
+        }
+
+        info!("Created service user: {}", SERVICE_USER);
+    }
+
+    // Membership in the docker group grants access to the Docker socket
+    let output = AsyncCommand::new("usermod")
+    .args(["-aG", "docker", SERVICE_USER])
+    .output()
+    .await
+    .context("Failed to run usermod")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("Failed to add {} to the docker group: {}", SERVICE_USER, stderr.trim());
+    }
+
+    info!("Added {} to the docker group", SERVICE_USER);
+
+    grant_hosts_file_access().await;
+    Ok(())
+}
+
+/// The hosts file is rewritten in place, so an ACL entry is enough to let the service user update it
+async fn grant_hosts_file_access() {
+    let output = AsyncCommand::new("setfacl")
+    .args(["-m", &format!("u:{}:rw", SERVICE_USER), HOSTS_FILE_PATH])
+    .output()
+    .await;
+
+    match output {
+        Ok(output) if output.status.success() => {
+            info!("Granted {} write access to {}", SERVICE_USER, HOSTS_FILE_PATH);
+        }
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!("Failed to grant {} write access to {}: {}", SERVICE_USER, HOSTS_FILE_PATH, stderr.trim());
+            warn!("Hosts file updates will fail; reinstall with --keep-root or disable hosts management");
+        }
+        Err(e) => {
+            warn!("Failed to run setfacl, {} cannot write {}: {}", SERVICE_USER, HOSTS_FILE_PATH, e);
+            warn!("Install the acl package, reinstall with --keep-root or disable hosts management");
+        }
+    }
+}
+
+fn service_user() -> Result<Option<User>> {
+    User::from_name(SERVICE_USER).map_err(|e| anyhow!("Failed to look up user {}: {}", SERVICE_USER, e))
+}
+
+/// Hand the directories, and anything a previous root installation left in them, to the service user
+pub fn set_directory_owner(dirs: &[PathBuf]) -> Result<()> {
+    let user = service_user()?.ok_or_else(|| anyhow!("Service user {} does not exist", SERVICE_USER))?;
+
+    for dir in dirs {
+        chown_recursive(dir, &user)
+            .with_context(|| format!("Failed to change owner of {}", dir.display()))?;
+        debug!("Changed owner of {} to {}", dir.display(), SERVICE_USER);
+    }
+
+    info!("Service directories are owned by {}", SERVICE_USER);
+    Ok(())
+}
+
+/// Give directories the install handed to the service user back to it after a root command
+/// wrote into them; directories of a --keep-root install stay root-owned
+pub fn restore_service_ownership(dirs: &[PathBuf]) {
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+    let Ok(Some(user)) = service_user() else {
+        return;
+    };
+
+    for dir in dirs {
+        let owned = std::fs::metadata(dir).map(|metadata| metadata.uid() == user.uid.as_raw()).unwrap_or(false);
+        if owned {
+            if let Err(e) = chown_recursive(dir, &user) {
+                warn!("Failed to give {} back to {}: {}", dir.display(), SERVICE_USER, e);
+            }
+        }
+    }
+}
+
+fn chown_recursive(path: &Path, user: &User) -> Result<()> {
+    // lchown changes a symlink itself, not the file it points to
+    lchown(path, Some(user.uid.as_raw()), Some(user.gid.as_raw()))?;
+
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_recursive(&entry?.path(), user)?;
+        }
+    }
+
+    Ok(())
+}
+
 // Check if we're running as root
 pub fn check_privileges() -> Result<()> {
     unsafe {
@@ -153,3 +348,34 @@ pub fn check_privileges() -> Result<()> {
 
     Ok(())
 }
+
+// Read the SELinux mode from selinuxfs; a missing file means SELinux is disabled
+pub fn check_selinux_state() -> SelinuxState {
+    match std::fs::read_to_string(SELINUX_ENFORCE_PATH) {
+        Ok(content) => match content.trim() {
+            "1" => SelinuxState::Enforcing,
+            _ => SelinuxState::Permissive,
+        },
+        Err(_) => SelinuxState::Disabled,
+    }
+}
+
+// Explain how to label the bind-mounted directories when SELinux would block NGINX from reading them
+pub fn warn_if_selinux_enforcing() {
+    if check_selinux_state() != SelinuxState::Enforcing {
+        return;
+    }
+
+    let mounted_dirs = crate::installer::get_mounted_dirs();
+
+    warn!("SELinux is in enforcing mode. The NGINX container may be denied access to bind-mounted files.");
+    warn!("If NGINX fails with 'Permission denied' on certificates or config, relabel the mounted directories:");
+    for dir in &mounted_dirs {
+        warn!("    chcon -Rt svirt_sandbox_file_t {}", dir.display());
+    }
+    warn!("To keep the labels across relabeling, register them permanently:");
+    for dir in &mounted_dirs {
+        warn!("    semanage fcontext -a -t svirt_sandbox_file_t \"{}(/.*)?\"", dir.display());
+        warn!("    restorecon -Rv {}", dir.display());
+    }
+}
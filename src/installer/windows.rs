@@ -1,9 +1,18 @@
-use anyhow::{Result, bail};
-use log::{info, warn};
+use anyhow::{Result, anyhow, bail};
+use log::{info, warn, Log};
+use std::path::Path;
 use std::ptr;
+use tokio::process::Command;
 use widestring::U16CString;
 use windows::core::{PCWSTR, PWSTR};
-use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Foundation::{HANDLE, PSID};
+use windows::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE,
+};
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE, KEY_WRITE,
+    REG_DWORD, REG_EXPAND_SZ, REG_OPTION_NON_VOLATILE,
+};
 use windows::Win32::System::Services::*;
 use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
 use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY, SC_HANDLE};
@@ -11,6 +20,150 @@ use windows::Win32::Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVAT
 const SERVICE_NAME: &str = "Autolocalhost";
 const SERVICE_DISPLAY_NAME: &str = "Autolocalhost Service";
 const SERVICE_DESCRIPTION: &str = "Local development environment automation service";
+const EVENT_SOURCE_KEY: &str = "SYSTEM\\CurrentControlSet\\Services\\EventLog\\Application\\Autolocalhost";
+
+/// Exclude a directory from Windows Defender real-time scanning, so certificate
+/// files aren't scanned on every read
+pub async fn add_defender_exclusion(path: &Path) -> Result<()> {
+    run_defender_cmdlet("Add-MpPreference", path).await?;
+    info!("Added Windows Defender exclusion: {}", path.display());
+    Ok(())
+}
+
+/// Remove a Windows Defender exclusion added during installation
+pub async fn remove_defender_exclusion(path: &Path) -> Result<()> {
+    run_defender_cmdlet("Remove-MpPreference", path).await?;
+    info!("Removed Windows Defender exclusion: {}", path.display());
+    Ok(())
+}
+
+async fn run_defender_cmdlet(cmdlet: &str, path: &Path) -> Result<()> {
+    // Single-quoted PowerShell string: only the quote itself needs escaping
+    let quoted_path = format!("'{}'", path.display().to_string().replace('\'', "''"));
+    let command = format!("{} -ExclusionPath {}", cmdlet, quoted_path);
+
+    let output = Command::new("powershell.exe")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &command])
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run powershell.exe: {}", e))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            cmdlet,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Register the Application event log source, so Event Viewer shows the service's entries
+pub async fn register_event_source() -> Result<()> {
+    let exe_path = crate::installer::get_install_dir().join("autolocalhost.exe");
+    let key_path = U16CString::from_str(EVENT_SOURCE_KEY)?;
+    let message_file = U16CString::from_os_str(exe_path.as_os_str())?;
+    let message_file_bytes: Vec<u8> = message_file
+        .as_slice_with_nul()
+        .iter()
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect();
+    let types_supported = (EVENTLOG_ERROR_TYPE.0 | EVENTLOG_WARNING_TYPE.0 | EVENTLOG_INFORMATION_TYPE.0) as u32;
+    let event_message_file = U16CString::from_str("EventMessageFile")?;
+    let types_supported_name = U16CString::from_str("TypesSupported")?;
+
+    let mut key = HKEY::default();
+    unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR(key_path.as_ptr()),
+            0,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            None,
+            &mut key,
+            None,
+        )?;
+    }
+
+    let result = unsafe {
+        RegSetValueExW(key, PCWSTR(event_message_file.as_ptr()), 0, REG_EXPAND_SZ, Some(&message_file_bytes)).and_then(|_| {
+            RegSetValueExW(key, PCWSTR(types_supported_name.as_ptr()), 0, REG_DWORD, Some(&types_supported.to_le_bytes()))
+        })
+    };
+    unsafe {
+        let _ = RegCloseKey(key);
+    }
+    result?;
+
+    info!("Registered event log source {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// Remove the event log source registered during installation
+pub async fn unregister_event_source() -> Result<()> {
+    let key_path = U16CString::from_str(EVENT_SOURCE_KEY)?;
+    unsafe {
+        RegDeleteKeyW(HKEY_LOCAL_MACHINE, PCWSTR(key_path.as_ptr()))?;
+    }
+
+    info!("Removed event log source {}", SERVICE_NAME);
+    Ok(())
+}
+
+/// `log` sink that writes through the wrapped logger and also reports errors,
+/// warnings and info messages to the Application event log
+struct EventLogger {
+    inner: env_logger::Logger,
+    source: HANDLE,
+}
+
+impl Log for EventLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.inner.matches(record) {
+            return;
+        }
+        self.inner.log(record);
+
+        let event_type = match record.level() {
+            log::Level::Error => EVENTLOG_ERROR_TYPE,
+            log::Level::Warn => EVENTLOG_WARNING_TYPE,
+            log::Level::Info => EVENTLOG_INFORMATION_TYPE,
+            _ => return,
+        };
+
+        if let Ok(message) = U16CString::from_str(record.args().to_string()) {
+            unsafe {
+                let _ = ReportEventW(self.source, event_type, 0, 0, PSID::default(), 0, Some(&[PCWSTR(message.as_ptr())]), None);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Install the service logger, also reporting to the event log when the source can be opened
+pub fn init_service_logger(inner: env_logger::Logger) -> Result<()> {
+    let max_level = inner.filter();
+    let source_name = U16CString::from_str(SERVICE_NAME)?;
+
+    let logger: Box<dyn Log> = match unsafe { RegisterEventSourceW(PCWSTR::null(), PCWSTR(source_name.as_ptr())) } {
+        Ok(source) => Box::new(EventLogger { inner, source }),
+        Err(_) => Box::new(inner),
+    };
+
+    log::set_boxed_logger(logger)?;
+    log::set_max_level(max_level);
+    Ok(())
+}
 
 pub async fn is_service_running() -> Result<bool> {
     let manager = open_service_manager()?;
@@ -27,6 +180,12 @@ pub async fn is_service_running() -> Result<bool> {
     Ok(status.dwCurrentState == SERVICE_RUNNING)
 }
 
+/// Human-readable service status
+pub async fn service_status() -> Result<String> {
+    let state = if is_service_running().await? { "running" } else { "not running" };
+    Ok(format!("{} is {}", SERVICE_NAME, state))
+}
+
 pub async fn stop_service() -> Result<()> {
     let manager = open_service_manager()?;
     let service = open_service(&manager, SERVICE_NAME)?;
@@ -1,9 +1,21 @@
+mod benchmark;
+mod cleanup;
+mod config;
+mod diff;
 mod docker;
+mod doctor;
 mod hosts;
 mod installer;
+mod maintenance;
+mod migrate;
 mod nginx;
+mod regen_cert;
+mod selftest;
 mod ssl;
+mod state;
+mod status;
 mod utils;
+mod validate;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -28,11 +40,65 @@ enum Commands {
     /// Start the autolocalhost service
     Start,
     /// Install autolocalhost as a system service
-    Install,
+    Install {
+        /// Run the service as root instead of a dedicated autolocalhost user (unix only)
+        #[arg(long)]
+        keep_root: bool,
+    },
     /// Uninstall the autolocalhost system service
-    Uninstall,
+    Uninstall {
+        /// Seconds to wait for Docker during nginx container cleanup before skipping it
+        #[arg(long, default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Remove the managed NGINX container and network, keeping the service installed
+    Cleanup,
     /// Show version information
     Version,
+    /// Show the state of the managed NGINX container
+    Status,
+    /// Measure how quickly container events are turned into hosts/NGINX updates
+    Benchmark {
+        /// Number of containers started per run
+        #[arg(long, default_value_t = 1)]
+        containers: u32,
+        /// Number of measurement runs
+        #[arg(long, default_value_t = 5)]
+        runs: u32,
+    },
+    /// Route a throwaway container through the running service to verify the install
+    Selftest,
+    /// Migrate configuration and data written by an older version
+    Migrate {
+        /// Version that wrote the existing data, e.g. 0.2.6
+        from_version: String,
+    },
+    /// Serve a maintenance page for all domains instead of proxying to containers
+    Maintenance {
+        #[arg(value_enum)]
+        mode: MaintenanceMode,
+    },
+    /// Show how the hosts file and NGINX config would change for the current containers.
+    /// Exits with 0 when nothing would change, 1 when something would, 2 on errors.
+    Diff,
+    /// Delete and regenerate one domain's certificate, reloading NGINX if it is running
+    RegenCert {
+        /// Domain whose certificate is replaced
+        domain: String,
+    },
+    /// Check the environment for problems, e.g. mount paths Docker Desktop does not share
+    Doctor,
+    /// Check the autolocalhost labels of a container without starting the service
+    ValidateLabels {
+        /// Container name or ID
+        container: String,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum MaintenanceMode {
+    On,
+    Off,
 }
 
 #[tokio::main]
@@ -41,29 +107,84 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Commands::Start => run_service().await,
-        Commands::Install => installer::install().await,
-        Commands::Uninstall => installer::uninstall().await,
+        Commands::Install { keep_root } => installer::install(keep_root).await,
+        Commands::Uninstall { timeout } => {
+            installer::uninstall(std::time::Duration::from_secs(timeout)).await
+        }
+        Commands::Cleanup => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            cleanup::run().await
+        }
         Commands::Version => {
             println!("autolocalhost {}", VERSION);
             Ok(())
         }
+        Commands::Status => status::run().await,
+        Commands::Benchmark { containers, runs } => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            benchmark::run(containers, runs).await
+        }
+        Commands::Selftest => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            selftest::run().await
+        }
+        Commands::Migrate { from_version } => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+            migrate::run(&from_version).await
+        }
+        Commands::Maintenance { mode } => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            maintenance::run(matches!(mode, MaintenanceMode::On)).await
+        }
+        Commands::RegenCert { domain } => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            regen_cert::run(&domain).await
+        }
+        Commands::Doctor => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            doctor::run().await
+        }
+        Commands::ValidateLabels { container } => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            validate::run(&container).await
+        }
+        Commands::Diff => {
+            env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "warn"));
+            match diff::run().await {
+                Ok(false) => Ok(()),
+                Ok(true) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Error: {:#}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
     }
 }
 
 async fn run_service() -> Result<()> {
-    // Initialize logger with default configuration
-    env_logger::init_from_env(env_logger::Env::default().filter_or("RUST_LOG", "info"));
+    // Initialize logger with default configuration (on Windows it also feeds the event log)
+    let logger = env_logger::Builder::from_env(env_logger::Env::default().filter_or("RUST_LOG", "info")).build();
+    installer::init_service_logger(logger)?;
 
     info!("Starting autolocalhost service...");
+    if !config::get().manage_hosts {
+        info!("Hosts file management is disabled, domains must resolve through your own DNS");
+    }
 
     // Ensure required directories exist
     let config_dir = installer::get_config_dir();
     let data_dir = installer::get_data_dir();
+    let nginx_config_dir = installer::get_nginx_config_dir();
+    let nginx_cache_dir = installer::get_nginx_cache_dir();
     let certs_dir = installer::get_certs_dir();
     let ca_dir = installer::get_ca_dir();
     let log_dir = installer::get_log_dir();
     let nginx_log_dir = installer::get_nginx_log_dir();
 
+    // Certificates must not silently fall back to another directory than the one configured
+    installer::validate_dir_overrides().await?;
+
     if let Err(e) = fs::create_dir_all(&config_dir).await {
         warn!(
             "Failed to create config directory {}: {}",
@@ -80,6 +201,22 @@ async fn run_service() -> Result<()> {
         );
     }
 
+    if let Err(e) = fs::create_dir_all(&nginx_config_dir).await {
+        warn!(
+            "Failed to create nginx config directory {}: {}",
+            nginx_config_dir.display(),
+            e
+        );
+    }
+
+    if let Err(e) = fs::create_dir_all(&nginx_cache_dir).await {
+        warn!(
+            "Failed to create nginx cache directory {}: {}",
+            nginx_cache_dir.display(),
+            e
+        );
+    }
+
     if let Err(e) = fs::create_dir_all(&certs_dir).await {
         warn!(
             "Failed to create certs directory {}: {}",
@@ -108,6 +245,11 @@ async fn run_service() -> Result<()> {
         );
     }
 
+    // Bring data written by another version up to date
+    if let Err(e) = migrate::run_if_needed().await {
+        warn!("Failed to migrate data: {}", e);
+    }
+
     // Ensure nginx template exists
     if let Err(e) = nginx::config_generator::ensure_nginx_template_exists().await {
         warn!("Failed to create nginx template: {}", e);
@@ -123,6 +265,7 @@ async fn run_service() -> Result<()> {
     let docker = match docker::connect_docker().await {
         Ok(client) => {
             info!("Connected to Docker API");
+            doctor::warn_if_mounts_unshared(&client).await;
             Arc::new(client)
         }
         Err(err) => {
@@ -134,10 +277,10 @@ async fn run_service() -> Result<()> {
     // Create a channel for graceful shutdown
     let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
 
-    // Handle Ctrl+C signal
+    // Handle Ctrl+C, and SIGTERM sent by systemd when stopping the service
     tokio::spawn(async move {
-        if let Err(e) = signal::ctrl_c().await {
-            error!("Failed to listen for ctrl+c signal: {}", e);
+        if let Err(e) = shutdown_signal().await {
+            error!("Failed to listen for shutdown signals: {}", e);
         }
         info!("Received shutdown signal, cleaning up...");
         let _ = shutdown_tx.send(());
@@ -152,3 +295,18 @@ async fn run_service() -> Result<()> {
     info!("Autolocalhost service stopped");
     Ok(())
 }
+
+/// Wait for Ctrl+C or, on unix, SIGTERM
+async fn shutdown_signal() -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = signal::ctrl_c() => result,
+            _ = terminate.recv() => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    signal::ctrl_c().await
+}
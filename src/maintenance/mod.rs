@@ -0,0 +1,69 @@
+use anyhow::{Result, anyhow};
+use crate::installer::get_data_dir;
+use log::info;
+use std::path::PathBuf;
+use tokio::fs;
+
+const MAINTENANCE_FLAG_FILE: &str = "maintenance.flag";
+
+/// Path of the flag file that switches every domain to the maintenance page
+pub fn get_flag_file_path() -> PathBuf {
+    get_data_dir().join(MAINTENANCE_FLAG_FILE)
+}
+
+/// Whether maintenance mode is on. Checked on every render so that toggling it
+/// does not require a service restart; AUTOLOCALHOST_MAINTENANCE takes precedence
+/// over the flag file.
+pub fn is_enabled() -> bool {
+    crate::config::env_bool("AUTOLOCALHOST_MAINTENANCE")
+        .unwrap_or_else(|| get_flag_file_path().exists())
+}
+
+/// Turn maintenance mode on or off. A running service watches the flag file and applies the
+/// change itself; otherwise the NGINX configuration is updated once from here.
+pub async fn run(enable: bool) -> Result<()> {
+    let flag_path = get_flag_file_path();
+
+    if enable {
+        if let Some(parent) = flag_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&flag_path, b"").await
+            .map_err(|e| anyhow!("Failed to create {}: {}", flag_path.display(), e))?;
+        println!("Maintenance mode enabled");
+    } else {
+        match fs::remove_file(&flag_path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow!("Failed to remove {}: {}", flag_path.display(), e)),
+        }
+        println!("Maintenance mode disabled");
+    }
+
+    if crate::config::env_bool("AUTOLOCALHOST_MAINTENANCE").is_some() {
+        println!("Note: AUTOLOCALHOST_MAINTENANCE is set and overrides the flag file");
+    }
+
+    // Reconfiguring from here too would race the service's own update
+    if crate::installer::is_service_running().await.unwrap_or(false) {
+        crate::installer::restore_service_ownership();
+        println!("The autolocalhost service applies the change within a few seconds");
+        return Ok(());
+    }
+
+    let result = match crate::docker::try_connect_docker().await {
+        Ok(docker) => {
+            info!("Applying maintenance mode to the NGINX configuration");
+            crate::docker::reconfigure_once(&docker).await
+                .map(|()| println!("NGINX configuration updated"))
+        }
+        Err(e) => {
+            println!("Docker unavailable ({}), the change applies on the next configuration update", e);
+            Ok(())
+        }
+    };
+
+    // The flag file, nginx.conf and state.json were written as root, even by a failed update
+    crate::installer::restore_service_ownership();
+    result
+}
@@ -0,0 +1,137 @@
+use anyhow::{Result, anyhow};
+use crate::installer::get_data_dir;
+use log::{debug, info};
+use std::path::PathBuf;
+use tokio::fs;
+
+const VERSION_FILE_NAME: &str = "version";
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version assumed for installations from before the version file existed
+const UNVERSIONED: &str = "0.0.0";
+
+type MigrateFn = fn() -> Result<()>;
+
+/// Migration chain, oldest first: each step upgrades on-disk data written by
+/// `from` to the format expected by `to`. Steps must be idempotent.
+fn migrations() -> Vec<(&'static str, &'static str, MigrateFn)> {
+    vec![
+        ("0.2.6", "0.2.7", remove_legacy_nginx_conf),
+    ]
+}
+
+/// Migrate on-disk data from an explicit version to the running one
+pub async fn run(from_version: &str) -> Result<()> {
+    parse_version(from_version)
+        .ok_or_else(|| anyhow!("Invalid version '{}', expected <major>.<minor>.<patch>", from_version))?;
+
+    let applied = apply(from_version)?;
+    write_version_file().await?;
+
+    println!("Applied {} migration(s) from {} to {}", applied, from_version, CURRENT_VERSION);
+    Ok(())
+}
+
+/// Run pending migrations when the data was last used by another version
+pub async fn run_if_needed() -> Result<()> {
+    let recorded = match fs::read_to_string(get_version_file_path()).await {
+        Ok(version) => version.trim().to_string(),
+        Err(_) => String::from(UNVERSIONED),
+    };
+
+    if recorded == CURRENT_VERSION {
+        debug!("Data directory is up to date ({})", recorded);
+        return Ok(());
+    }
+
+    info!("Data directory was last used by version {}, migrating to {}", recorded, CURRENT_VERSION);
+    apply(&recorded)?;
+    write_version_file().await
+}
+
+fn apply(from_version: &str) -> Result<usize> {
+    let steps = pending(migrations(), from_version, CURRENT_VERSION);
+
+    for (from, to, migrate) in &steps {
+        info!("Migrating data from {} to {}", from, to);
+        migrate().map_err(|e| anyhow!("Migration from {} to {} failed: {}", from, to, e))?;
+    }
+
+    Ok(steps.len())
+}
+
+/// Steps that lead from `from_version` up to `to_version`, in chain order
+fn pending(
+    chain: Vec<(&'static str, &'static str, MigrateFn)>,
+    from_version: &str,
+    to_version: &str,
+) -> Vec<(&'static str, &'static str, MigrateFn)> {
+    let (Some(from), Some(to)) = (parse_version(from_version), parse_version(to_version)) else {
+        return Vec::new();
+    };
+
+    chain
+        .into_iter()
+        .filter(|(_, step_to, _)| {
+            parse_version(step_to).map(|step_to| step_to > from && step_to <= to).unwrap_or(false)
+        })
+        .collect()
+}
+
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version.trim().trim_start_matches('v').splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    // Ignore pre-release and build suffixes such as "1.0.0-beta"
+    let patch = parts.next()?.split(['-', '+']).next()?.parse().ok()?;
+    Some((major, minor, patch))
+}
+
+fn get_version_file_path() -> PathBuf {
+    get_data_dir().join(VERSION_FILE_NAME)
+}
+
+async fn write_version_file() -> Result<()> {
+    let path = get_version_file_path();
+    fs::create_dir_all(get_data_dir()).await?;
+    fs::write(&path, CURRENT_VERSION).await
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+}
+
+/// nginx.conf moved from the data directory into its own mounted `nginx` subdirectory
+fn remove_legacy_nginx_conf() -> Result<()> {
+    let legacy_path = get_data_dir().join("nginx.conf");
+
+    match std::fs::remove_file(&legacy_path) {
+        Ok(()) => info!("Removed legacy {}", legacy_path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(anyhow!("Failed to remove {}: {}", legacy_path.display(), e)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop() -> Result<()> {
+        Ok(())
+    }
+
+    #[test]
+    fn selects_steps_between_versions() {
+        let chain: Vec<(&'static str, &'static str, MigrateFn)> = vec![
+            ("0.1.0", "0.2.0", noop),
+            ("0.2.0", "0.9.0", noop),
+            ("0.9.0", "1.0.0", noop),
+        ];
+
+        let steps: Vec<_> = pending(chain, "0.2.0", "1.0.0-beta")
+            .into_iter()
+            .map(|(from, to, _)| (from, to))
+            .collect();
+
+        assert_eq!(steps, vec![("0.2.0", "0.9.0"), ("0.9.0", "1.0.0")]);
+    }
+}
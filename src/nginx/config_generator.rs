@@ -1,21 +1,363 @@
 use anyhow::{Result, anyhow};
 use handlebars::Handlebars;
-use log::{info, debug};
+use log::{info, debug, warn};
 use serde::Serialize;
 use tokio::fs;
-use std::path::Path;
-use crate::docker::container_info::ContainerInfo;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use crate::config::Profile;
+use crate::docker::container_info::{is_valid_format_name, ContainerInfo, HeaderMap};
+use crate::nginx::config_linter::{lint_config, LintSeverity, NginxLint};
+use crate::nginx::template_helpers;
+use crate::utils::atomic_file::write_atomic;
 
 // Template data structure for Handlebars
 #[derive(Serialize)]
 struct TemplateData {
-    containers: Vec<ContainerInfo>,
+    containers: Vec<ContainerTemplateData>,
+    // Server blocks of the plain HTTP and SSL ports, one per domain and port
+    servers: Vec<ServerTemplateData>,
+    ssl_servers: Vec<ServerTemplateData>,
+    // Path of the proxy health check location, if enabled
+    health_endpoint: Option<String>,
+    // Custom log formats from the configuration file
+    log_formats: BTreeMap<String, String>,
+    // Serve the maintenance page for every domain
+    maintenance: bool,
+    // Override the Server response header through the headers-more module
+    server_header_override: bool,
+    // Replacement Server header value, empty to remove the header
+    server_header: String,
+    // Reference the generated DH parameters from SSL servers
+    dhparam: bool,
+    // http-level tuning from the selected profile
+    profile: TuningProfile,
+    // nginx.worker_processes from the configuration file
+    worker_processes: String,
+    // nginx.worker_connections from the configuration file, or the profile's value
+    worker_connections: u32,
+    // ssl_protocols from ssl.min_version, for the http block and servers without their own
+    ssl_protocols: &'static str,
+    // The proxy image is OpenResty, whose user and file layout differ from the nginx image
+    openresty: bool,
+}
+
+// Directive values behind AUTOLOCALHOST_PROFILE
+#[derive(Serialize)]
+struct TuningProfile {
+    name: &'static str,
+    error_log_level: &'static str,
+    worker_connections: u32,
+    sendfile: bool,
+    keepalive_timeout: u32,
+    keepalive_requests: u32,
+    gzip: bool,
+    open_file_cache: bool,
+    // ETag and Last-Modified revalidation; off in dev so browsers always refetch
+    client_caching: bool,
+}
+
+impl TuningProfile {
+    fn new(profile: Profile) -> Self {
+        match profile {
+            Profile::Dev => Self {
+                name: "dev",
+                error_log_level: "info",
+                worker_connections: 1024,
+                sendfile: false,
+                keepalive_timeout: 65,
+                keepalive_requests: 100,
+                gzip: false,
+                open_file_cache: false,
+                client_caching: false,
+            },
+            Profile::Perf => Self {
+                name: "perf",
+                error_log_level: "warn",
+                worker_connections: 4096,
+                sendfile: true,
+                keepalive_timeout: 30,
+                keepalive_requests: 1000,
+                gzip: true,
+                open_file_cache: true,
+                client_caching: true,
+            },
+        }
+    }
+}
+
+// Per-container template data: the container itself plus values derived from it
+#[derive(Serialize, Clone)]
+struct ContainerTemplateData {
+    #[serde(flatten)]
+    container: ContainerInfo,
+    // Distinct internal ports, one upstream block is declared per port
+    upstream_ports: Vec<u16>,
+    // Address NGINX proxies to: the container name, or its explicit upstream IP
+    upstream_host: String,
+    // Proxy through named upstream blocks, needed for a fallback server or slow start
+    upstream_block: bool,
+    // Slow start period for the upstream server, e.g. "30s"
+    slow_start: Option<String>,
+    // Per entry of ports / ssl_ports: whether its listen directive carries `reuseport`
+    reuseport: Vec<bool>,
+    ssl_reuseport: Vec<bool>,
+    // proxy_cache zone of the domain, when the cache label is set
+    cache_zone: Option<String>,
+    // Whether this container declares the zone's proxy_cache_path; zones are shared per domain
+    declare_cache_zone: bool,
+    // Whether this container declares the map block of its header_map label
+    declare_header_map: bool,
+}
+
+impl ContainerTemplateData {
+    fn new(container: &ContainerInfo, known_log_formats: &HashSet<String>) -> Self {
+        let mut upstream_ports: Vec<u16> = container.ports.iter()
+            .chain(container.ssl_ports.iter())
+            .map(|port| port.internal)
+            .collect();
+        upstream_ports.sort_unstable();
+        upstream_ports.dedup();
+
+        let upstream_host = match container.upstream_ip {
+            Some(IpAddr::V6(ip)) => format!("[{}]", ip),
+            Some(IpAddr::V4(ip)) => ip.to_string(),
+            None => container.name.clone(),
+        };
+
+        let mut container = container.clone();
+
+        // Only reference log formats that are actually declared
+        if let Some(format) = &container.access_log_format {
+            if !known_log_formats.contains(format) {
+                warn!(
+                    "Unknown access_log_format '{}' for {} (known formats: {}), using the default access log",
+                    format,
+                    container.name,
+                    known_log_formats.iter().cloned().collect::<Vec<_>>().join(", ")
+                );
+                container.access_log_format = None;
+            }
+        }
+
+        let slow_start = container.upstream_slow_start.map(|period| format!("{}s", period.as_secs()));
+        let cache_zone = container.cache_valid.as_ref().map(|_| cache_zone_name(&container.domain));
+
+        Self {
+            upstream_block: container.fallback_upstream.is_some() || slow_start.is_some(),
+            container,
+            upstream_ports,
+            upstream_host,
+            slow_start,
+            reuseport: Vec::new(),
+            ssl_reuseport: Vec::new(),
+            cache_zone,
+            declare_cache_zone: false,
+            declare_header_map: false,
+        }
+    }
+}
+
+// One location of a server block: a container and the port pair routed through it
+#[derive(Serialize)]
+struct LocationTemplateData {
+    #[serde(flatten)]
+    container: ContainerTemplateData,
+    internal: u16,
+    external: u16,
+}
+
+// One server block: a domain on one listen port, with a location per container routed there
+#[derive(Serialize)]
+struct ServerTemplateData {
+    domain: String,
+    external: u16,
+    // Names of every container routed to the domain
+    network_aliases: Vec<String>,
+    // Whether the listen directive carries `reuseport`
+    reuseport: bool,
+    // Listen with HTTP/2 when any location serves gRPC
+    http2: bool,
+    // Declare the stub fallback location when any location ignores upstream errors
+    ignore_errors: bool,
+    // Logging and TLS settings, taken from the first container of the domain
+    access_log_format: Option<String>,
+    ssl_early_data: bool,
+    ssl_protocols: Option<String>,
+    ssl_stapling: bool,
+    client_ca: Option<PathBuf>,
+    client_verify_optional: bool,
+    locations: Vec<LocationTemplateData>,
+}
+
+impl ServerTemplateData {
+    fn new(location: LocationTemplateData, reuseport: bool) -> Self {
+        let container = &location.container.container;
+
+        Self {
+            domain: container.domain.clone(),
+            external: location.external,
+            network_aliases: container.network_aliases.clone(),
+            reuseport,
+            http2: container.grpc_enabled,
+            ignore_errors: container.ignore_errors,
+            access_log_format: container.access_log_format.clone(),
+            ssl_early_data: container.ssl_early_data,
+            ssl_protocols: container.ssl_protocols.clone(),
+            ssl_stapling: container.ssl_stapling,
+            client_ca: container.client_ca.clone(),
+            client_verify_optional: container.client_verify_optional,
+            locations: vec![location],
+        }
+    }
+
+    fn add_location(&mut self, location: LocationTemplateData) {
+        let container = &location.container.container;
+
+        for alias in &container.network_aliases {
+            if !self.network_aliases.contains(alias) {
+                self.network_aliases.push(alias.clone());
+            }
+        }
+        self.http2 |= container.grpc_enabled;
+        self.ignore_errors |= container.ignore_errors;
+        self.locations.push(location);
+    }
+}
+
+/// Group the plain HTTP or SSL ports of the containers into server blocks. Containers sharing
+/// a domain under distinct paths get one server per port with a location each; NGINX would
+/// ignore a second server block with the same name and port.
+fn group_servers(containers: &[ContainerTemplateData], ssl: bool) -> Vec<ServerTemplateData> {
+    let mut servers: Vec<ServerTemplateData> = Vec::new();
+
+    for data in containers {
+        let (ports, reuseport) = if ssl {
+            (&data.container.ssl_ports, &data.ssl_reuseport)
+        } else {
+            (&data.container.ports, &data.reuseport)
+        };
+
+        for (index, port) in ports.iter().enumerate() {
+            let location = LocationTemplateData {
+                container: data.clone(),
+                internal: port.internal,
+                external: port.external,
+            };
+            let domain = &data.container.domain;
+            let existing = servers.iter_mut()
+                .find(|server| !domain.is_empty() && &server.domain == domain && server.external == port.external);
+
+            match existing {
+                Some(server) => server.add_location(location),
+                None => servers.push(ServerTemplateData::new(location, reuseport.get(index).copied().unwrap_or(false))),
+            }
+        }
+    }
+
+    servers
+}
+
+/// Warn when containers sharing a domain disagree on settings of its server blocks, which
+/// come from the first container of the domain
+fn warn_on_server_setting_conflicts(containers: &[ContainerTemplateData]) {
+    let mut first: HashMap<&str, &ContainerInfo> = HashMap::new();
+
+    for data in containers {
+        let container = &data.container;
+        if container.domain.is_empty() {
+            continue;
+        }
+
+        let Some(owner) = first.get(container.domain.as_str()) else {
+            first.insert(&container.domain, container);
+            continue;
+        };
+
+        let settings = |c: &ContainerInfo| {
+            (
+                c.access_log_format.clone(),
+                c.ssl_early_data,
+                c.ssl_protocols.clone(),
+                c.ssl_stapling,
+                c.client_ca.clone(),
+                c.client_verify_optional,
+            )
+        };
+        if settings(owner) != settings(container) {
+            warn!(
+                "Container {} shares {} with {} but sets different TLS or access log labels, using those of {}",
+                container.name, container.domain, owner.name, owner.name
+            );
+        }
+    }
+}
+
+/// proxy_cache zone name for a domain: `app.test` becomes `cache_app_test`
+fn cache_zone_name(domain: &str) -> String {
+    let name: String = domain
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    format!("cache_{}", name)
+}
+
+/// Let the first container of every cache zone declare it. Containers of one domain routed
+/// under different paths share the zone, and NGINX rejects a zone declared twice.
+fn mark_cache_zone_declarations(containers: &mut [ContainerTemplateData]) {
+    let mut declared = HashSet::new();
+
+    for data in containers.iter_mut() {
+        if let Some(zone) = &data.cache_zone {
+            data.declare_cache_zone = declared.insert(zone.clone());
+        }
+    }
+}
+
+/// Let the first container using a map variable declare it; NGINX rejects a variable declared
+/// twice. Containers sharing an identical map reuse it, a conflicting map is dropped.
+fn mark_header_map_declarations(containers: &mut [ContainerTemplateData]) {
+    let mut declared: HashMap<String, (String, HeaderMap)> = HashMap::new();
+
+    for data in containers.iter_mut() {
+        let Some(map) = &data.container.header_map else {
+            continue;
+        };
+
+        match declared.get(&map.target) {
+            None => {
+                declared.insert(map.target.clone(), (data.container.name.clone(), map.clone()));
+                data.declare_header_map = true;
+            }
+            Some((_, existing)) if existing == map => {}
+            Some((owner, _)) => {
+                warn!(
+                    "Ignoring map_headers of {}: ${} is already mapped differently by {}",
+                    data.container.name, map.target, owner
+                );
+                data.container.header_map = None;
+            }
+        }
+    }
+}
+
+/// Set `reuseport` on the first listen directive of every port. NGINX accepts the parameter only
+/// once per address:port, and server blocks of several containers may listen on the same port.
+fn mark_reuseport_listeners(containers: &mut [ContainerTemplateData]) {
+    let mut marked = HashSet::new();
+
+    for data in containers.iter_mut() {
+        data.reuseport = data.container.ports.iter().map(|port| marked.insert(port.external)).collect();
+        data.ssl_reuseport = data.container.ssl_ports.iter().map(|port| marked.insert(port.external)).collect();
+    }
 }
 
 /// NGINX configuration generator
 pub struct ConfigGenerator<'a> {
     containers: &'a [ContainerInfo],
-    template_path: String,
+    template_path: PathBuf,
+    listen_reuseport: bool,
 }
 
 impl<'a> ConfigGenerator<'a> {
@@ -25,49 +367,191 @@ impl<'a> ConfigGenerator<'a> {
 
         Self {
             containers,
-            template_path:  String::from(template_path.to_str().unwrap()),
+            template_path,
+            listen_reuseport: crate::config::get().listen_reuseport,
         }
     }
 
     /// Prepare template data
-    fn prepare_template_data(&self) -> TemplateData {
-        TemplateData {
-            containers: self.containers.to_vec(),
-        }
-    }
+    fn prepare_template_data(&self, template_source: &str) -> TemplateData {
+        let log_formats = custom_log_formats();
 
-    /// Generate NGINX configuration file
-    pub async fn generate_config(&self, output_file: &str) -> Result<()> {
-        debug!("Generating NGINX config from template: {}", self.template_path);
+        let mut known_log_formats = declared_log_formats(template_source);
+        known_log_formats.extend(log_formats.keys().cloned());
+        let server_header = server_header();
 
-        // Check if template file exists
-        if !Path::new(&self.template_path).exists() {
-            return Err(anyhow!("NGINX template file not found: {}", self.template_path));
+        let mut containers: Vec<ContainerTemplateData> = self.containers.iter()
+            .map(|container| ContainerTemplateData::new(container, &known_log_formats))
+            .collect();
+        // Server blocks follow the highest priority label of their domain, then the domain;
+        // locations within a server follow their own priority, then the container name
+        let mut domain_priority: HashMap<String, i32> = HashMap::new();
+        for data in &containers {
+            let priority = domain_priority.entry(data.container.domain.clone()).or_insert(i32::MIN);
+            *priority = (*priority).max(data.container.priority);
         }
+        containers.sort_by(|a, b| {
+            let key = |data: &ContainerTemplateData| {
+                (
+                    std::cmp::Reverse(domain_priority[&data.container.domain]),
+                    data.container.domain.clone(),
+                    std::cmp::Reverse(data.container.priority),
+                    data.container.name.clone(),
+                )
+            };
+            key(a).cmp(&key(b))
+        });
+        if self.listen_reuseport {
+            mark_reuseport_listeners(&mut containers);
+        }
+        mark_cache_zone_declarations(&mut containers);
+        mark_header_map_declarations(&mut containers);
+        warn_on_server_setting_conflicts(&containers);
+        let servers = group_servers(&containers, false);
+        let ssl_servers = group_servers(&containers, true);
 
-        // Read template file
-        let template_source = fs::read_to_string(&self.template_path).await?;
+        let profile = TuningProfile::new(crate::config::get().profile);
+        let nginx = &crate::config::get().nginx;
 
+        TemplateData {
+            containers,
+            servers,
+            ssl_servers,
+            health_endpoint: health_endpoint_path(),
+            log_formats,
+            maintenance: crate::maintenance::is_enabled(),
+            server_header_override: server_header.is_some(),
+            server_header: server_header.unwrap_or_default(),
+            dhparam: !crate::config::get().dhparam_skip,
+            worker_processes: nginx.worker_processes.clone(),
+            worker_connections: nginx.worker_connections.unwrap_or(profile.worker_connections),
+            ssl_protocols: crate::config::get().ssl.min_version.protocols(),
+            openresty: crate::config::get().is_openresty_image(),
+            profile,
+        }
+    }
+
+    /// Render the NGINX configuration from a template source
+    pub fn render_config(&self, template_source: &str) -> Result<String> {
         // Setup Handlebars
         let mut handlebars = Handlebars::new();
 
+        // The output is an NGINX config, not HTML, so values must be emitted verbatim
+        handlebars.register_escape_fn(handlebars::no_escape);
+
+        // Value and structure helpers for custom templates, see template_helpers
+        template_helpers::register(&mut handlebars);
+
         // Register template
         handlebars.register_template_string("nginx_template", template_source)?;
 
         // Prepare data
-        let data = self.prepare_template_data();
+        let data = self.prepare_template_data(template_source);
 
         // Render template
         let config = handlebars.render("nginx_template", &data)?;
 
-        // Write output file
-        fs::write(output_file, config).await?;
+        Ok(config)
+    }
+
+    /// Render the NGINX configuration from the template file
+    pub async fn render(&self) -> Result<String> {
+        debug!("Generating NGINX config from template: {}", self.template_path.display());
 
-        info!("NGINX configuration generated: {}", output_file);
+        // Check if template file exists
+        if !self.template_path.exists() {
+            return Err(anyhow!("NGINX template file not found: {}", self.template_path.display()));
+        }
+
+        // Read template file
+        let template_source = fs::read_to_string(&self.template_path).await?;
+
+        self.render_config(&template_source)
+    }
+
+    /// Render the NGINX configuration from the template file, with the lint findings for it
+    pub async fn render_with_lints(&self) -> Result<(String, Vec<NginxLint>)> {
+        let config = self.render().await?;
+        let lints = lint_config(&config);
+        Ok((config, lints))
+    }
+
+    /// Generate NGINX configuration file
+    pub async fn generate_config(&self, output_file: &Path) -> Result<()> {
+        let (config, lints) = self.render_with_lints().await?;
+
+        // Report potentially unsafe directive combinations before writing
+        for lint in lints {
+            match lint.severity {
+                LintSeverity::Info => debug!("NGINX config lint ({}): {}", lint.severity, lint.message),
+                LintSeverity::Warning => warn!("NGINX config lint ({}): {}", lint.severity, lint.message),
+            }
+        }
+
+        // Write output file atomically so NGINX never reads a half-written config
+        write_atomic(output_file, config.as_bytes()).await?;
+
+        info!("NGINX configuration generated: {}", output_file.display());
         Ok(())
     }
 }
 
+/// Names of the log formats declared with `log_format` in the template
+fn declared_log_formats(template_source: &str) -> HashSet<String> {
+    template_source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("log_format "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .filter(|name| is_valid_format_name(name))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Custom log formats from the configuration, skipping ones that can't be emitted safely
+fn custom_log_formats() -> BTreeMap<String, String> {
+    crate::config::get()
+        .log_formats
+        .iter()
+        .filter(|(name, format)| {
+            let valid = is_valid_format_name(name) && !format.contains(['\'', '\n', '\r']);
+            if !valid {
+                warn!("Ignoring invalid log format '{}' from configuration", name);
+            }
+            valid
+        })
+        .map(|(name, format)| (name.clone(), format.clone()))
+        .collect()
+}
+
+/// Server header override from the configuration, if it can be emitted safely
+fn server_header() -> Option<String> {
+    let value = crate::config::get().server_header.as_ref()?.trim();
+
+    if value.contains(['"', '\\', '\n', '\r', '$']) {
+        warn!("Ignoring invalid Server header value '{}'", value);
+        return None;
+    }
+
+    Some(value.to_string())
+}
+
+/// Health check path from the configuration, if the endpoint is enabled and the path is usable
+fn health_endpoint_path() -> Option<String> {
+    let config = crate::config::get();
+
+    if !config.health_endpoint_enabled {
+        return None;
+    }
+
+    let path = config.health_endpoint_path.trim();
+    if !path.starts_with('/') || path.contains(|c: char| c.is_whitespace() || c == ';' || c == '{' || c == '}') {
+        warn!("Invalid health endpoint path '{}', health endpoint disabled", path);
+        return None;
+    }
+
+    Some(path.to_string())
+}
+
 /// Create the default NGINX template if it doesn't exist
 pub async fn ensure_nginx_template_exists() -> Result<()> {
     //let template_path = "nginx.template.conf";
@@ -78,91 +562,875 @@ pub async fn ensure_nginx_template_exists() -> Result<()> {
         return Ok(());
     }
 
-    info!("Creating default NGINX template: {}", template_path.to_str().unwrap());
+    info!("Creating default NGINX template: {}", template_path.display());
+
+    fs::write(template_path, DEFAULT_TEMPLATE).await?;
 
-    let template_content = r#"# Основные настройки
+    Ok(())
+}
+
+/// Default NGINX template written to the config directory on first start
+pub const DEFAULT_TEMPLATE: &str = r#"# Основные настройки
+{{#if openresty}}
+# OpenResty images have no nginx user and keep mime.types under their own prefix
+user nobody;
+{{else}}
 user nginx;
-worker_processes auto;
-error_log /var/log/nginx/error.log warn;
+{{/if}}
+worker_processes {{worker_processes}};
+error_log /var/log/nginx/error.log {{profile.error_log_level}};
 pid /var/run/nginx.pid;
+{{#if server_header_override}}
+load_module modules/ngx_http_headers_more_filter_module.so;
+{{/if}}
 
 events {
-    worker_connections 1024;
+    worker_connections {{worker_connections}};
 }
 
 # HTTP настройки для обычного HTTP трафика
 http {
-    include /etc/nginx/mime.types;
+    include {{#if openresty}}/usr/local/openresty/nginx/conf{{else}}/etc/nginx{{/if}}/mime.types;
     default_type application/octet-stream;
+    {{#if server_header_override}}
+
+    # more_set_headers/more_clear_headers come from the headers-more module, which the official
+    # nginx image does not include. Use an image that ships it, or NGINX will fail to start.
+    server_tokens off;
+    {{#if server_header}}
+    more_set_headers "Server: {{server_header}}";
+    {{else}}
+    more_clear_headers Server;
+    {{/if}}
+    {{/if}}
 
     log_format main '$remote_addr - $remote_user [$time_local] "$request" '
                     '$status $body_bytes_sent "$http_referer" '
                     '"$http_user_agent" "$http_x_forwarded_for"';
 
+    log_format timing '$remote_addr [$time_local] "$request" $status $body_bytes_sent '
+                      'rt=$request_time urt=$upstream_response_time ssl=$ssl_protocol';
+    {{#each log_formats}}
+    log_format {{@key}} '{{this}}';
+    {{/each}}
+
     access_log /var/log/nginx/access.log main;
 
-    sendfile on;
+    # Global TLS floor (ssl.min_version); servers repeat it unless their ssl_protocols label narrows it
+    ssl_protocols {{ssl_protocols}};
+
+    # Forward WebSocket upgrades, close the upstream connection for plain requests
+    map $http_upgrade $connection_upgrade {
+        default upgrade;
+        ''      close;
+    }
+
+    # Tuning profile: {{profile.name}}
+    sendfile {{#if profile.sendfile}}on{{else}}off{{/if}};
     tcp_nopush on;
     tcp_nodelay on;
 
-    keepalive_timeout 65;
+    keepalive_timeout {{profile.keepalive_timeout}};
+    keepalive_requests {{profile.keepalive_requests}};
     types_hash_max_size 2048;
+    {{#if profile.gzip}}
+
+    gzip on;
+    gzip_vary on;
+    gzip_proxied any;
+    gzip_comp_level 5;
+    gzip_min_length 1024;
+    gzip_types text/plain text/css text/xml application/json application/javascript application/xml application/rss+xml image/svg+xml;
+    {{/if}}
+    {{#if profile.open_file_cache}}
+
+    open_file_cache max=1000 inactive=20s;
+    open_file_cache_valid 30s;
+    open_file_cache_min_uses 2;
+    open_file_cache_errors on;
+    {{/if}}
+    {{#unless profile.client_caching}}
+
+    etag off;
+    if_modified_since off;
+    {{/unless}}
 
     {{#each containers}}
     # Container ID: {{id}}
-    {{#each ports}}
+    {{#if upstream_block}}
+    {{#each upstream_ports}}
+    upstream {{../name}}_{{this}} {
+        {{#if ../slow_start}}
+        # slow_start is an NGINX Plus parameter, open source NGINX rejects it
+        {{/if}}
+        server {{../upstream_host}}:{{this}}{{#if ../slow_start}} slow_start={{../slow_start}}{{/if}};
+        {{#if ../fallback_upstream}}
+        server {{../fallback_upstream.host}}:{{../fallback_upstream.port}} backup;
+        {{/if}}
+    }
+    {{/each}}
+    {{/if}}
+    {{#if mirror}}
+    # Mirror target as an upstream, so the mirror location's proxy_pass needs no resolver
+    upstream {{name}}_mirror {
+        server {{mirror.host}}:{{mirror.port}};
+    }
+    {{/if}}
+    {{#if declare_cache_zone}}
+    proxy_cache_path /var/cache/nginx/autolocalhost/{{cache_zone}} levels=1:2 keys_zone={{cache_zone}}:10m max_size=1g inactive=60m use_temp_path=off;
+    {{/if}}
+    {{#if declare_header_map}}
+    # map_headers of {{name}}: {{header_map.header}} from ${{header_map.source}}
+    map ${{header_map.source}} ${{header_map.target}} {
+        {{#if header_map.default}}
+        default '{{header_map.default}}';
+        {{/if}}
+        {{#each header_map.values}}
+        '{{@key}}' '{{this}}';
+        {{/each}}
+    }
+    {{/if}}
+    {{/each}}
+    {{#each servers}}
     server {
-        listen {{external}};
-        server_name {{../domain}};
+        listen {{external}}{{#if http2}} http2{{/if}}{{#if reuseport}} reuseport{{/if}};
+        server_name {{domain}}{{#each network_aliases}} {{this}}{{/each}};
 
+        {{#if access_log_format}}
+        access_log /var/log/nginx/{{domain}}.access.log {{access_log_format}};
+
+        {{/if}}
+        {{#if @root.health_endpoint}}
+        location = {{@root.health_endpoint}} {
+            access_log off;
+            default_type text/plain;
+            return 200 "ok";
+        }
+
+        {{/if}}
+        {{#if @root.maintenance}}
         location / {
-            proxy_pass http://{{../name}}:{{internal}};
+            default_type text/html;
+            add_header Retry-After 300 always;
+            return 503 '<!DOCTYPE html><html><head><title>Maintenance</title></head><body><h1>{{domain}} is under maintenance</h1><p>Please try again later.</p></body></html>';
+        }
+        {{else}}
+        {{#if ignore_errors}}
+        # Stub answer for the locations of containers whose upstream is down
+        location @fallback {
+            default_type text/plain;
+            return 200 "Service temporarily unavailable - autolocalhost stub response\n";
+        }
+
+        {{/if}}
+        {{#each locations}}
+        {{#if grpc_enabled}}
+        location / {
+            # gRPC-Web is plain HTTP/1.1, so hand it over to a regular proxy location
+            error_page 418 = @grpc_web;
+            if ($http_content_type ~* "^application/grpc-web") {
+                return 418;
+            }
+
+            grpc_pass grpc://{{upstream_host}}:{{internal}};
+            {{#unless disable_proxy_headers}}
+            grpc_set_header Host $host;
+            grpc_set_header X-Real-IP $remote_addr;
+            grpc_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            {{/unless}}
+        }
+
+        location @grpc_web {
+            proxy_pass http://{{upstream_host}}:{{internal}};
+            {{#unless disable_proxy_headers}}
             proxy_set_header Host $host;
             proxy_set_header X-Real-IP $remote_addr;
             proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
             proxy_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
         }
+        {{else}}
+        {{#if mirror}}
+        # Shadow traffic from the mirror label; responses from the mirror are discarded
+        location = /{{name}}_mirror {
+            internal;
+            proxy_pass http://{{name}}_mirror$request_uri;
+        }
+
+        {{/if}}
+        location {{location_path}} {
+            {{#if ignore_errors}}
+            error_page 502 503 504 = @fallback;
+            {{/if}}
+            {{#if lua_block}}
+            # Lua handler from the lua_block label (OpenResty image only)
+            content_by_lua_block {
+{{lua_block}}
+            }
+            {{else if try_files}}
+            # Static files from the NGINX container's filesystem instead of proxying
+            root {{try_files_root}};
+            try_files{{#each try_files}} {{this}}{{/each}};
+            {{else if upstream_http2}}
+            # h2c upstream: the gRPC module speaks cleartext HTTP/2 to any backend (nginx 1.13.10+)
+            grpc_pass grpc://{{#if upstream_block}}{{name}}_{{else}}{{upstream_host}}:{{/if}}{{internal}};
+            {{#unless disable_proxy_headers}}
+            grpc_set_header Host $host;
+            grpc_set_header X-Real-IP $remote_addr;
+            grpc_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            grpc_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
+            {{else}}
+            proxy_pass http://{{#if upstream_block}}{{name}}_{{else}}{{upstream_host}}:{{/if}}{{internal}}{{#if strip_path}}/{{/if}};
+            {{#unless disable_proxy_headers}}
+            proxy_set_header Host $host;
+            proxy_set_header X-Real-IP $remote_addr;
+            proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            proxy_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
+            {{#if websocket}}
+            proxy_http_version 1.1;
+            proxy_set_header Upgrade $http_upgrade;
+            proxy_set_header Connection $connection_upgrade;
+            {{/if}}
+            {{#if proxy_redirect.from}}
+            proxy_redirect {{proxy_redirect.from}} {{proxy_redirect.to}};
+            {{/if}}
+            {{#if sub_filters}}
+            # Rewrite the response body; the upstream must not compress it for sub_filter to apply
+            proxy_set_header Accept-Encoding "";
+            {{#each sub_filters}}
+            sub_filter '{{this.[0]}}' '{{this.[1]}}';
+            {{/each}}
+            sub_filter_once off;
+            sub_filter_types *;
+            {{/if}}
+            {{#if header_map}}
+            proxy_set_header {{header_map.header}} ${{header_map.target}};
+            {{/if}}
+            {{#if cache_zone}}
+            proxy_cache {{cache_zone}};
+            proxy_cache_valid 200 301 302 {{cache_valid}};
+            add_header X-Cache-Status $upstream_cache_status always;
+            {{/if}}
+            {{#if mirror}}
+            mirror /{{name}}_mirror;
+            {{/if}}
+            {{/if}}
+            {{#if proxy_connect_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_connect_timeout {{proxy_connect_timeout}}s;
+            {{/if}}
+            {{#if proxy_read_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_read_timeout {{proxy_read_timeout}}s;
+            {{/if}}
+            {{#if proxy_send_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_send_timeout {{proxy_send_timeout}}s;
+            {{/if}}
+            {{#if (eq proxy_request_buffering false)}}
+            proxy_request_buffering off;
+            {{/if}}
+            {{#with security_headers}}
+            {{#if content_type_options}}
+            add_header X-Content-Type-Options {{content_type_options}} always;
+            {{/if}}
+            {{#if frame_options}}
+            add_header X-Frame-Options {{frame_options}} always;
+            {{/if}}
+            {{#if referrer_policy}}
+            add_header Referrer-Policy "{{referrer_policy}}" always;
+            {{/if}}
+            {{#if permissions_policy}}
+            add_header Permissions-Policy "{{permissions_policy}}" always;
+            {{/if}}
+            {{/with}}
+            {{#if cors_origin}}
+            # Answer CORS preflight here; add_header inside "if" replaces the location's headers, so repeat them
+            if ($request_method = OPTIONS) {
+                add_header Access-Control-Allow-Origin "{{cors_origin}}" always;
+                add_header Access-Control-Allow-Methods "GET, POST, PUT, PATCH, DELETE, OPTIONS" always;
+                add_header Access-Control-Allow-Headers $http_access_control_request_headers always;
+                {{#unless (eq cors_origin "*")}}
+                add_header Access-Control-Allow-Credentials true always;
+                add_header Vary Origin always;
+                {{/unless}}
+                add_header Access-Control-Max-Age 86400 always;
+                return 204;
+            }
+            # Drop the upstream's own CORS headers so each one is only sent once
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_hide_header Access-Control-Allow-Origin;
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_hide_header Access-Control-Allow-Credentials;
+            add_header Access-Control-Allow-Origin "{{cors_origin}}" always;
+            {{#unless (eq cors_origin "*")}}
+            add_header Access-Control-Allow-Credentials true always;
+            add_header Vary Origin always;
+            {{/unless}}
+            {{/if}}
+            {{#each proxy_pass_headers}}
+            proxy_pass_header {{this}};
+            {{/each}}
+            {{#each proxy_hide_headers}}
+            proxy_hide_header {{this}};
+            {{/each}}
+        }
+        {{/if}}
+        {{/each}}
+        {{/if}}
     }
     {{/each}}
-    {{#each ssl_ports}}
+    {{#each ssl_servers}}
     server {
-        listen {{external}} ssl;
-        server_name {{../domain}};
+        listen {{external}} ssl{{#if http2}} http2{{/if}}{{#if reuseport}} reuseport{{/if}};
+        server_name {{domain}}{{#each network_aliases}} {{this}}{{/each}};
 
-        ssl_certificate /etc/ssl/certs/{{../domain}}.fullchain.crt;
-        ssl_certificate_key /etc/ssl/certs/{{../domain}}.key;
+        ssl_certificate /etc/ssl/certs/{{domain}}.fullchain.crt;
+        ssl_certificate_key /etc/ssl/certs/{{domain}}.key;
 
         ssl_session_cache shared:le_nginx_SSL:10m;
         ssl_session_timeout 1440m;
         ssl_session_tickets off;
 
-        ssl_protocols TLSv1.2 TLSv1.3;
+        {{#if ssl_early_data}}
+        # TLS 1.3 0-RTT: early data can be replayed, so the upstream gets Early-Data: 1 to reject unsafe requests (425)
+        ssl_early_data on;
+        {{/if}}
+
+        ssl_protocols {{#if ssl_protocols}}{{ssl_protocols}}{{else}}{{@root.ssl_protocols}}{{/if}};
         ssl_prefer_server_ciphers off;
 
         ssl_ciphers "ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:ECDHE-ECDSA-CHACHA20-POLY1305:ECDHE-RSA-CHACHA20-POLY1305:DHE-RSA-AES128-GCM-SHA256:DHE-RSA-AES256-GCM-SHA384";
 
+        {{#if @root.dhparam}}
         ssl_dhparam /etc/ssl/certs/dhparams.crt;
+        {{/if}}
+
+        {{#if ssl_stapling}}
+        # The local CA has no public OCSP responder, so stapling usually finds nothing to staple.
+        # Set ssl_stapling off if a strict client rejects the handshake.
+        ssl_stapling on;
+        ssl_stapling_verify on;
+        ssl_trusted_certificate /etc/ssl/certs/{{domain}}.fullchain.crt;
+        resolver 8.8.8.8 valid=300s;
 
+        {{/if}}
+        {{#if client_ca}}
+        # Mutual TLS: client certificates are verified against this CA
+        ssl_client_certificate /etc/ssl/certs/{{domain}}.client-ca.crt;
+        ssl_verify_client {{#if client_verify_optional}}optional{{else}}on{{/if}};
+
+        {{/if}}
+        {{#if access_log_format}}
+        access_log /var/log/nginx/{{domain}}.access.log {{access_log_format}};
+
+        {{/if}}
+        {{#if @root.health_endpoint}}
+        location = {{@root.health_endpoint}} {
+            access_log off;
+            default_type text/plain;
+            return 200 "ok";
+        }
+
+        {{/if}}
+        {{#if @root.maintenance}}
+        location / {
+            default_type text/html;
+            add_header Retry-After 300 always;
+            return 503 '<!DOCTYPE html><html><head><title>Maintenance</title></head><body><h1>{{domain}} is under maintenance</h1><p>Please try again later.</p></body></html>';
+        }
+        {{else}}
+        {{#if ignore_errors}}
+        # Stub answer for the locations of containers whose upstream is down
+        location @fallback {
+            default_type text/plain;
+            return 200 "Service temporarily unavailable - autolocalhost stub response\n";
+        }
+
+        {{/if}}
+        {{#each locations}}
+        {{#if grpc_enabled}}
         location / {
-            proxy_pass http://{{../name}}:{{internal}};
+            # gRPC-Web is plain HTTP/1.1, so hand it over to a regular proxy location
+            error_page 418 = @grpc_web;
+            if ($http_content_type ~* "^application/grpc-web") {
+                return 418;
+            }
+
+            grpc_pass grpc://{{upstream_host}}:{{internal}};
+            {{#unless disable_proxy_headers}}
+            grpc_set_header Host $host;
+            grpc_set_header X-Real-IP $remote_addr;
+            grpc_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            {{/unless}}
+        }
+
+        location @grpc_web {
+            proxy_pass http://{{upstream_host}}:{{internal}};
+            {{#unless disable_proxy_headers}}
+            proxy_set_header Host $host;
+            proxy_set_header X-Real-IP $remote_addr;
+            proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            proxy_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
+
+            {{#unless disable_proxy_headers}}
+            proxy_set_header X-Forwarded-Port {{external}};
+            proxy_set_header X-Forwarded-Ssl on;
+            proxy_set_header X-Https on;
+            proxy_set_header HTTPS "on";
+            {{/unless}}
+        }
+        {{else}}
+        {{#if mirror}}
+        # Shadow traffic from the mirror label; responses from the mirror are discarded
+        location = /{{name}}_mirror {
+            internal;
+            proxy_pass http://{{name}}_mirror$request_uri;
+        }
+
+        {{/if}}
+        location {{location_path}} {
+            {{#if ignore_errors}}
+            error_page 502 503 504 = @fallback;
+            {{/if}}
+            {{#if lua_block}}
+            # Lua handler from the lua_block label (OpenResty image only)
+            content_by_lua_block {
+{{lua_block}}
+            }
+            {{else if try_files}}
+            # Static files from the NGINX container's filesystem instead of proxying
+            root {{try_files_root}};
+            try_files{{#each try_files}} {{this}}{{/each}};
+            {{else if upstream_http2}}
+            # h2c upstream: the gRPC module speaks cleartext HTTP/2 to any backend (nginx 1.13.10+)
+            grpc_pass grpc://{{#if upstream_block}}{{name}}_{{else}}{{upstream_host}}:{{/if}}{{internal}};
+            {{#unless disable_proxy_headers}}
+            grpc_set_header Host $host;
+            grpc_set_header X-Real-IP $remote_addr;
+            grpc_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+            grpc_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
+            {{else}}
+            proxy_pass http://{{#if upstream_block}}{{name}}_{{else}}{{upstream_host}}:{{/if}}{{internal}}{{#if strip_path}}/{{/if}};
+            {{#unless disable_proxy_headers}}
             proxy_set_header Host $host;
             proxy_set_header X-Real-IP $remote_addr;
             proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
             proxy_set_header X-Forwarded-Proto $scheme;
+            {{/unless}}
+            {{#if websocket}}
+            proxy_http_version 1.1;
+            proxy_set_header Upgrade $http_upgrade;
+            proxy_set_header Connection $connection_upgrade;
+            {{/if}}
+            {{#if ssl_early_data}}
+            proxy_set_header Early-Data $ssl_early_data;
+            {{/if}}
+            {{#if proxy_redirect}}
+            {{#if proxy_redirect.from}}
+            proxy_redirect {{proxy_redirect.from}} {{proxy_redirect.to}};
+            {{else}}
+            proxy_redirect http://{{upstream_host}}:{{internal}}/ https://{{domain}}{{#unless (eq external 443)}}:{{external}}{{/unless}}/;
+            {{/if}}
+            {{/if}}
+            {{#if sub_filters}}
+            # Rewrite the response body; the upstream must not compress it for sub_filter to apply
+            proxy_set_header Accept-Encoding "";
+            {{#each sub_filters}}
+            sub_filter '{{this.[0]}}' '{{this.[1]}}';
+            {{/each}}
+            sub_filter_once off;
+            sub_filter_types *;
+            {{/if}}
+            {{#if header_map}}
+            proxy_set_header {{header_map.header}} ${{header_map.target}};
+            {{/if}}
+            {{#if cache_zone}}
+            proxy_cache {{cache_zone}};
+            proxy_cache_valid 200 301 302 {{cache_valid}};
+            add_header X-Cache-Status $upstream_cache_status always;
+            {{/if}}
+            {{#if mirror}}
+            mirror /{{name}}_mirror;
+            {{/if}}
+            {{/if}}
 
+            {{#unless disable_proxy_headers}}
             proxy_set_header X-Forwarded-Port {{external}};
             proxy_set_header X-Forwarded-Ssl on;
             proxy_set_header X-Https on;
             proxy_set_header HTTPS "on";
+            {{/unless}}
+            {{#if proxy_connect_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_connect_timeout {{proxy_connect_timeout}}s;
+            {{/if}}
+            {{#if proxy_read_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_read_timeout {{proxy_read_timeout}}s;
+            {{/if}}
+            {{#if proxy_send_timeout}}
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_send_timeout {{proxy_send_timeout}}s;
+            {{/if}}
+            {{#if (eq proxy_request_buffering false)}}
+            proxy_request_buffering off;
+            {{/if}}
+            {{#with security_headers}}
+            {{#if content_type_options}}
+            add_header X-Content-Type-Options {{content_type_options}} always;
+            {{/if}}
+            {{#if frame_options}}
+            add_header X-Frame-Options {{frame_options}} always;
+            {{/if}}
+            {{#if referrer_policy}}
+            add_header Referrer-Policy "{{referrer_policy}}" always;
+            {{/if}}
+            {{#if permissions_policy}}
+            add_header Permissions-Policy "{{permissions_policy}}" always;
+            {{/if}}
+            {{/with}}
+            {{#if cors_origin}}
+            # Answer CORS preflight here; add_header inside "if" replaces the location's headers, so repeat them
+            if ($request_method = OPTIONS) {
+                add_header Access-Control-Allow-Origin "{{cors_origin}}" always;
+                add_header Access-Control-Allow-Methods "GET, POST, PUT, PATCH, DELETE, OPTIONS" always;
+                add_header Access-Control-Allow-Headers $http_access_control_request_headers always;
+                {{#unless (eq cors_origin "*")}}
+                add_header Access-Control-Allow-Credentials true always;
+                add_header Vary Origin always;
+                {{/unless}}
+                add_header Access-Control-Max-Age 86400 always;
+                return 204;
+            }
+            # Drop the upstream's own CORS headers so each one is only sent once
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_hide_header Access-Control-Allow-Origin;
+            {{#if upstream_http2}}grpc{{else}}proxy{{/if}}_hide_header Access-Control-Allow-Credentials;
+            add_header Access-Control-Allow-Origin "{{cors_origin}}" always;
+            {{#unless (eq cors_origin "*")}}
+            add_header Access-Control-Allow-Credentials true always;
+            add_header Vary Origin always;
+            {{/unless}}
+            {{/if}}
+            {{#each proxy_pass_headers}}
+            proxy_pass_header {{this}};
+            {{/each}}
+            {{#each proxy_hide_headers}}
+            proxy_hide_header {{this}};
+            {{/each}}
         }
+        {{/if}}
+        {{/each}}
+        {{/if}}
     }
     {{/each}}
 
-    {{/each}}
 }
 "#;
 
-    fs::write(template_path, template_content).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::port_mapping::{PortMapping, UpstreamTarget};
 
-    Ok(())
+    fn ssl_container(name: &str, domain: &str, external: u16, internal: u16) -> ContainerInfo {
+        ContainerInfo {
+            id: format!("{}-id", name),
+            name: name.to_string(),
+            is_running: true,
+            domain: domain.to_string(),
+            location_path: String::from("/"),
+            ssl_ports: vec![PortMapping::new(external, internal)],
+            ..Default::default()
+        }
+    }
+
+    /// Split a rendered config into its `server { ... }` blocks
+    fn server_blocks(config: &str) -> Vec<String> {
+        config
+            .split("    server {")
+            .skip(1)
+            .map(|block| block.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn routes_domains_sharing_an_ssl_port_by_server_name() {
+        let containers = vec![
+            ssl_container("app-a", "a.test", 443, 8080),
+            ssl_container("app-b", "b.test", 443, 9090),
+        ];
+
+        let config = ConfigGenerator::new(&containers)
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let blocks = server_blocks(&config);
+
+        assert_eq!(blocks.len(), 2);
+        assert!(blocks.iter().all(|block| block.contains("listen 443 ssl;")));
+
+        let a = blocks.iter().find(|block| block.contains("server_name a.test;")).unwrap();
+        assert!(a.contains("ssl_certificate /etc/ssl/certs/a.test.fullchain.crt;"));
+        assert!(a.contains("ssl_certificate_key /etc/ssl/certs/a.test.key;"));
+        assert!(a.contains("proxy_pass http://app-a:8080;"));
+        assert!(!a.contains("b.test"));
+
+        let b = blocks.iter().find(|block| block.contains("server_name b.test;")).unwrap();
+        assert!(b.contains("ssl_certificate /etc/ssl/certs/b.test.fullchain.crt;"));
+        assert!(b.contains("ssl_certificate_key /etc/ssl/certs/b.test.key;"));
+        assert!(b.contains("proxy_pass http://app-b:9090;"));
+        assert!(!b.contains("a.test"));
+    }
+
+    #[test]
+    fn renders_ssl_only_container_without_plain_http_server() {
+        let containers = vec![ssl_container("secure", "secure.test", 443, 8443)];
+
+        let config = ConfigGenerator::new(&containers)
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let blocks = server_blocks(&config);
+
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].contains("listen 443 ssl;"));
+        assert!(blocks[0].contains("server_name secure.test;"));
+        assert!(blocks[0].contains("proxy_pass http://secure:8443;"));
+    }
+
+    #[test]
+    fn websocket_locations_use_the_connection_upgrade_map() {
+        let containers = vec![
+            ContainerInfo { websocket: true, ..ssl_container("ws", "ws.test", 443, 8080) },
+            ssl_container("plain", "plain.test", 443, 9090),
+        ];
+
+        let config = ConfigGenerator::new(&containers)
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let blocks = server_blocks(&config);
+
+        assert_eq!(config.matches("map $http_upgrade $connection_upgrade").count(), 1);
+        assert!(!config.contains("Connection \"upgrade\""));
+
+        let ws = blocks.iter().find(|block| block.contains("server_name ws.test;")).unwrap();
+        assert!(ws.contains("proxy_set_header Connection $connection_upgrade;"));
+
+        let plain = blocks.iter().find(|block| block.contains("server_name plain.test;")).unwrap();
+        assert!(!plain.contains("proxy_set_header Connection"));
+    }
+
+    #[test]
+    fn early_data_is_enabled_on_the_ssl_server_only() {
+        let mut container = ContainerInfo { ssl_early_data: true, ..ssl_container("app", "app.test", 443, 8080) };
+        container.ports = vec![PortMapping::new(80, 8080)];
+
+        let config = ConfigGenerator::new(&[container])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let blocks = server_blocks(&config);
+
+        let ssl = blocks.iter().find(|block| block.contains("listen 443 ssl;")).unwrap();
+        assert!(ssl.contains("ssl_early_data on;"));
+        assert!(ssl.contains("proxy_set_header Early-Data $ssl_early_data;"));
+
+        let plain = blocks.iter().find(|block| block.contains("listen 80;")).unwrap();
+        assert!(!plain.contains("early_data"));
+        assert!(lint_config(&config).iter().all(|lint| !lint.message.contains("ssl_early_data")));
+    }
+
+    #[test]
+    fn warns_when_unbuffered_requests_are_cached() {
+        let container = ContainerInfo {
+            proxy_request_buffering: Some(false),
+            cache_valid: Some(String::from("10m")),
+            ..ssl_container("app", "app.test", 443, 8080)
+        };
+
+        let config = ConfigGenerator::new(&[container])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+
+        assert!(lint_config(&config).iter().any(|lint| {
+            lint.severity == LintSeverity::Warning && lint.message.contains("proxy_request_buffering off is combined with proxy_cache")
+        }));
+    }
+
+    #[test]
+    fn mirrors_requests_to_an_internal_location() {
+        let container = ContainerInfo {
+            mirror: Some(UpstreamTarget::parse("shadow:9000").unwrap()),
+            ..ssl_container("app", "app.test", 443, 8080)
+        };
+
+        let config = ConfigGenerator::new(&[container])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+
+        assert!(config.contains("mirror /app_mirror;"));
+        assert!(config.contains("location = /app_mirror {\n            internal;\n            proxy_pass http://app_mirror$request_uri;"));
+
+        // proxy_pass with variables resolves its host at request time, which only works without
+        // a resolver directive when the host names an upstream block
+        let upstream = "upstream app_mirror {\n        server shadow:9000;\n    }";
+        assert!(config.contains(upstream));
+        for line in config.lines().map(str::trim).filter(|line| line.starts_with("proxy_pass") && line.contains('$')) {
+            let host = line.trim_start_matches("proxy_pass http://").split('$').next().unwrap();
+            assert!(config.contains(&format!("upstream {} {{", host)), "{} has no upstream block", host);
+        }
+    }
+
+    #[test]
+    fn emits_higher_priority_containers_first() {
+        let web = ssl_container("web", "c.test", 443, 7070);
+        let catch_all = ssl_container("catch-all", "a.test", 443, 8080);
+        let api = ContainerInfo { priority: 10, ..ssl_container("api", "b.test", 443, 9090) };
+
+        // Containers of equal priority are ordered by domain, whatever order they arrive in
+        let config = ConfigGenerator::new(&[web, catch_all, api])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let position = |domain: &str| config.find(&format!("server_name {};", domain)).unwrap();
+
+        assert!(position("b.test") < position("a.test"));
+        assert!(position("a.test") < position("c.test"));
+    }
+
+    #[test]
+    fn serves_containers_sharing_a_domain_from_one_server() {
+        let api = ContainerInfo {
+            location_path: String::from("/api/"),
+            network_aliases: vec![String::from("api.internal")],
+            ..ssl_container("api", "app.test", 443, 9090)
+        };
+        let web = ContainerInfo { ignore_errors: true, ..ssl_container("web", "app.test", 443, 8080) };
+        let admin = ssl_container("admin", "app.test", 8443, 7070);
+
+        let config = ConfigGenerator::new(&[web, api, admin])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+        let blocks = server_blocks(&config);
+        let block = |listen: &str| blocks.iter().find(|block| block.contains(listen)).unwrap();
+
+        assert_eq!(blocks.len(), 2);
+        let shared = block("listen 443 ssl;");
+        assert!(shared.contains("server_name app.test api.internal;"));
+        assert!(shared.contains("location /api/ {"));
+        assert!(shared.contains("proxy_pass http://api:9090;"));
+        assert!(shared.contains("proxy_pass http://web:8080;"));
+        assert_eq!(shared.matches("error_page 502 503 504 = @fallback;").count(), 1);
+        assert!(shared.contains("location @fallback {"));
+        assert!(block("listen 8443 ssl;").contains("proxy_pass http://admin:7070;"));
+    }
+
+    #[test]
+    fn maps_request_headers_from_an_http_level_map() {
+        let header_map = HeaderMap {
+            header: String::from("X-Tenant-ID"),
+            source: String::from("host"),
+            target: String::from("tenant"),
+            values: BTreeMap::from([(String::from("a.app.test"), String::from("a"))]),
+            default: Some(String::from("none")),
+        };
+        let api = ContainerInfo { header_map: Some(header_map.clone()), ..ssl_container("api", "a.app.test", 443, 9090) };
+        let web = ContainerInfo { header_map: Some(header_map), ..ssl_container("web", "b.app.test", 443, 8080) };
+
+        let config = ConfigGenerator::new(&[api, web])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+
+        assert_eq!(config.matches("map $host $tenant {").count(), 1);
+        assert!(config.contains("default 'none';"));
+        assert!(config.contains("'a.app.test' 'a';"));
+        assert_eq!(config.matches("proxy_set_header X-Tenant-ID $tenant;").count(), 2);
+    }
+
+    #[test]
+    fn declares_one_cache_zone_per_domain() {
+        let api = ContainerInfo {
+            location_path: String::from("/api/"),
+            cache_valid: Some(String::from("5m")),
+            ..ssl_container("api", "app.test", 443, 9090)
+        };
+        let web = ContainerInfo { cache_valid: Some(String::from("5m")), ..ssl_container("web", "app.test", 443, 8080) };
+
+        let config = ConfigGenerator::new(&[api, web])
+            .render_config(DEFAULT_TEMPLATE)
+            .unwrap();
+
+        // Both locations live in the domain's one server block and share its zone
+        let blocks = server_blocks(&config);
+        assert_eq!(blocks.len(), 1);
+        assert!(blocks[0].contains("location /api/ {"));
+        assert_eq!(blocks[0].matches("proxy_cache cache_app_test;").count(), 2);
+        assert_eq!(config.matches("keys_zone=cache_app_test:10m").count(), 1);
+        assert!(config.contains("proxy_cache_valid 200 301 302 5m;"));
+    }
+
+    #[test]
+    fn reuseport_is_set_once_per_listen_port() {
+        let mut plain = ssl_container("plain", "plain.test", 443, 8080);
+        plain.ports = vec![PortMapping::new(80, 8080)];
+        let containers = vec![
+            plain,
+            ssl_container("app-a", "a.test", 443, 8081),
+            ssl_container("app-b", "b.test", 8443, 8082),
+        ];
+
+        let render = |listen_reuseport: bool| {
+            ConfigGenerator { listen_reuseport, ..ConfigGenerator::new(&containers) }
+                .render_config(DEFAULT_TEMPLATE)
+                .unwrap()
+        };
+        let listens = |config: &str| -> Vec<String> {
+            config.lines()
+                .map(str::trim)
+                .filter(|line| line.starts_with("listen "))
+                .map(str::to_string)
+                .collect()
+        };
+
+        let config = render(true);
+        // Plain servers come first, then SSL servers ordered by domain: a.test, b.test, plain.test
+        assert_eq!(listens(&config), vec![
+            "listen 80 reuseport;",
+            "listen 443 ssl reuseport;",
+            "listen 8443 ssl reuseport;",
+            "listen 443 ssl;",
+        ]);
+        assert!(lint_config(&config).iter().all(|lint| !lint.message.contains("reuseport")));
+
+        assert!(listens(&render(false)).iter().all(|listen| !listen.contains("reuseport")));
+    }
+
+    #[test]
+    fn custom_templates_can_use_helpers() {
+        let mut plain = ssl_container("plain", "plain.local", 443, 3000);
+        plain.ssl_ports = Vec::new();
+        let containers = vec![ssl_container("App", "App.Test", 443, 8080), plain];
+
+        let template = "{{nginx_comment \"ports\"}} {{port_range start=8080 count=3}}\n\
+            {{#each containers}}{{lower domain}} {{upper name}} {{default access_log_format \"main\"}} \
+            {{domain_to_upstream domain}}={{#if (if_ssl this)}}ssl{{else}}plain{{/if}}\n{{/each}}";
+        let config = ConfigGenerator::new(&containers).render_config(template).unwrap();
+
+        assert_eq!(
+            config,
+            "# ports 8080,8081,8082\napp.test APP main App_Test_upstream=ssl\nplain.local PLAIN main plain_local_upstream=plain\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn generates_config_in_directory_with_spaces() {
+        let work_dir = std::env::temp_dir().join(format!("autolocalhost data {}", std::process::id()));
+        fs::create_dir_all(&work_dir).await.unwrap();
+
+        let template_path = work_dir.join("nginx.template.conf");
+        fs::write(&template_path, DEFAULT_TEMPLATE).await.unwrap();
+
+        let containers = vec![ssl_container("app", "app.test", 443, 8080)];
+        let generator = ConfigGenerator { template_path, ..ConfigGenerator::new(&containers) };
+        let output_path = work_dir.join("nginx.conf");
+        generator.generate_config(&output_path).await.unwrap();
+
+        let config = fs::read_to_string(&output_path).await.unwrap();
+        assert!(config.contains("server_name app.test;"));
+
+        let _ = fs::remove_dir_all(&work_dir).await;
+    }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::fmt;
+
+/// How serious a lint finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Info,
+    Warning,
+}
+
+impl fmt::Display for LintSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintSeverity::Info => write!(f, "info"),
+            LintSeverity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A potentially unsafe construct found in a rendered NGINX config
+#[derive(Debug, Clone)]
+pub struct NginxLint {
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+impl NginxLint {
+    fn new(severity: LintSeverity, message: String) -> Self {
+        Self { severity, message }
+    }
+}
+
+/// Scan a rendered NGINX config for known anti-patterns
+pub fn lint_config(config: &str) -> Vec<NginxLint> {
+    let mut lints = Vec::new();
+
+    for block in find_blocks(config, "server") {
+        let directives = directives(&block);
+        let server_name = directives.iter()
+            .find_map(|d| d.strip_prefix("server_name "))
+            .unwrap_or("<unnamed>")
+            .trim_end_matches(';');
+
+        // TLS terminated at NGINX but forwarded in cleartext
+        let has_ssl = directives.iter().any(|d| d.starts_with("ssl_certificate "));
+        if has_ssl && directives.iter().any(|d| d.starts_with("proxy_pass http://")) {
+            lints.push(NginxLint::new(
+                LintSeverity::Info,
+                format!("server {}: TLS is terminated at NGINX and proxied to the upstream over plain HTTP", server_name),
+            ));
+        }
+
+        // Headers without `always` are dropped from error responses
+        for directive in directives.iter().filter(|d| d.starts_with("add_header ")) {
+            if !directive.trim_end_matches(';').ends_with(" always") {
+                lints.push(NginxLint::new(
+                    LintSeverity::Warning,
+                    format!("server {}: '{}' is not sent on error responses (missing 'always')", server_name, directive),
+                ));
+            }
+        }
+
+        // 0-RTT is a TLS 1.3 feature
+        let early_data = directives.iter().any(|d| d == "ssl_early_data on;");
+        let protocols = directives.iter().find(|d| d.starts_with("ssl_protocols "));
+        if let Some(protocols) = protocols.filter(|d| early_data && !d.contains("TLSv1.3")) {
+            lints.push(NginxLint::new(
+                LintSeverity::Warning,
+                format!("server {}: ssl_early_data needs TLSv1.3, which '{}' does not enable", server_name, protocols),
+            ));
+        }
+
+        // Caching relies on buffering, streamed request bodies defeat it
+        let unbuffered = directives.iter().any(|d| d == "proxy_request_buffering off;");
+        if unbuffered && directives.iter().any(|d| d.starts_with("proxy_cache ")) {
+            lints.push(NginxLint::new(
+                LintSeverity::Warning,
+                format!("server {}: proxy_request_buffering off is combined with proxy_cache, caching does not work without buffering", server_name),
+            ));
+        }
+
+        // Rate limiting answers 503 unless told otherwise
+        let has_limit_req = directives.iter().any(|d| d.starts_with("limit_req "));
+        if has_limit_req && !directives.iter().any(|d| d.starts_with("limit_req_status 429")) {
+            lints.push(NginxLint::new(
+                LintSeverity::Warning,
+                format!("server {}: limit_req without 'limit_req_status 429' rejects requests with 503", server_name),
+            ));
+        }
+    }
+
+    // reuseport may only be set once per listen socket
+    let all_directives = directives(config);
+    let mut reuseport_ports = HashSet::new();
+    for directive in all_directives.iter().filter(|d| d.starts_with("listen ")) {
+        let mut params = directive.trim_end_matches(';').split_whitespace().skip(1);
+        let address = params.next().unwrap_or_default();
+        if params.any(|param| param == "reuseport") && !reuseport_ports.insert(address.to_string()) {
+            lints.push(NginxLint::new(
+                LintSeverity::Warning,
+                format!("'reuseport' is set on more than one 'listen {}', NGINX will refuse the duplicate listen options", address),
+            ));
+        }
+    }
+
+    // Upstream keepalive only works over HTTP/1.1
+    let has_keepalive_upstream = find_blocks(config, "upstream")
+        .iter()
+        .any(|block| directives(block).iter().any(|d| d.starts_with("keepalive ")));
+    if has_keepalive_upstream && !all_directives.iter().any(|d| d.starts_with("proxy_http_version 1.1")) {
+        lints.push(NginxLint::new(
+            LintSeverity::Warning,
+            String::from("upstream keepalive is configured but no location sets 'proxy_http_version 1.1', so connections are not reused"),
+        ));
+    }
+
+    lints
+}
+
+/// Extract the bodies of all `<name> ... { ... }` blocks, wherever they are nested
+fn find_blocks(config: &str, name: &str) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<(String, usize)> = None;
+    let mut depth = 0usize;
+
+    for line in config.lines() {
+        let trimmed = strip_comment(line).trim();
+
+        if current.is_none() && trimmed.ends_with('{') {
+            let keyword = trimmed.split_whitespace().next().unwrap_or("");
+            if keyword == name {
+                current = Some((String::new(), depth));
+            }
+        }
+
+        depth += trimmed.matches('{').count();
+        depth = depth.saturating_sub(trimmed.matches('}').count());
+
+        if let Some((body, start_depth)) = current.as_mut() {
+            body.push_str(trimmed);
+            body.push('\n');
+
+            if depth == *start_depth {
+                blocks.push(body.clone());
+                current = None;
+            }
+        }
+    }
+
+    blocks
+}
+
+/// Directive lines (`name args;`) of a config fragment, without comments
+fn directives(fragment: &str) -> Vec<String> {
+    fragment
+        .lines()
+        .map(|line| strip_comment(line).trim())
+        .filter(|line| line.ends_with(';'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(index) if !line[..index].contains(['"', '\'']) => &line[..index],
+        _ => line,
+    }
+}
@@ -1,19 +1,89 @@
 use anyhow::{anyhow, Result};
 use bollard::container::{
-    Config, CreateContainerOptions, ListContainersOptions, RemoveContainerOptions,
-    StartContainerOptions,
+    Config, CreateContainerOptions, KillContainerOptions, ListContainersOptions, LogsOptions,
+    RemoveContainerOptions, StartContainerOptions, UploadToContainerOptions,
 };
 use bollard::image::{CreateImageOptions, ListImagesOptions};
 use bollard::models::{
-    HostConfig, Mount, MountTypeEnum, PortBinding, RestartPolicy, RestartPolicyNameEnum,
+    HostConfig, Ipam, IpamConfig, Mount, MountTypeEnum, PortBinding, RestartPolicy, RestartPolicyNameEnum,
 };
-use bollard::network::{CreateNetworkOptions, ListNetworksOptions};
+use bollard::network::{CreateNetworkOptions, InspectNetworkOptions, ListNetworksOptions};
+use bollard::errors::Error as BollardError;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::auth::DockerCredentials;
 use bollard::Docker;
 use futures_util::StreamExt;
-use log::{debug, info, warn};
+use crate::config::{ConfigDelivery, RegistryAuth};
+use crate::utils::port_mapping::{check_port_conflicts, PortMapping};
+use crate::utils::tar_archive;
+use log::{debug, error, info, warn};
 use std::collections::HashMap;
 use std::env;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Directory inside the container where the generated config directory is mounted
+const NGINX_CONFIG_MOUNT_DIR: &str = "/etc/nginx/autolocalhost";
+/// Directory inside the container holding the proxy_cache_path of every cached domain
+const NGINX_CACHE_MOUNT_DIR: &str = "/var/cache/nginx/autolocalhost";
+/// How a regenerated config reaches NGINX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateStrategy {
+    /// The running container re-reads its config
+    Reload,
+    /// A new container is created, e.g. to publish a different set of ports
+    Recreate,
+}
+
+/// Label (under the configured prefix) marking the NGINX container and network created by autolocalhost
+pub const NGINX_CONTAINER_LABEL: &str = "managed-nginx-container";
+/// Attempts to start the freshly created container, e.g. while a previous container releases its ports
+const START_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled after each failed attempt
+const START_RETRY_DELAY_MS: u64 = 500;
+/// Interval between info-level progress lines while the NGINX image is pulled
+const PULL_PROGRESS_INTERVAL_SECS: u64 = 3;
+/// File in the data directory recording the topology (ports, network, image, mounts) the running NGINX container was created with
+const TOPOLOGY_FILE_NAME: &str = "nginx-topology";
+/// Unique local IPv6 subnet of the managed network when AUTOLOCALHOST_NETWORK_IPV6 is set
+const NETWORK_IPV6_SUBNET: &str = "fd00:a10c:a1::/64";
+/// Log lines shown when the container cannot be started
+const START_FAILURE_LOG_LINES: &str = "20";
+/// Time NGINX gets to parse its config before the container is checked to still be running
+const STARTUP_CHECK_DELAY_MS: u64 = 1000;
+/// Directives of modules the official nginx image lacks, with the module that provides them
+const MODULE_DIRECTIVES: &[(&str, &str)] = &[
+    ("more_set_headers", "headers-more"),
+    ("more_clear_headers", "headers-more"),
+    ("_by_lua", "Lua (OpenResty)"),
+    ("lua_", "Lua (OpenResty)"),
+    ("brotli", "ngx_brotli"),
+    ("geoip2", "ngx_http_geoip2"),
+];
+
+/// Result of removing the managed network
+#[derive(Debug, PartialEq, Eq)]
+pub enum NetworkRemoval {
+    Removed,
+    NotFound,
+    /// Containers still attached to the network, which is kept
+    InUse(Vec<String>),
+}
+
+/// Host directory bind-mounted into the NGINX container. Kept structured rather than
+/// as a `source:target:mode` string, since host paths may contain colons (`C:\...`) or spaces.
+struct BindMount {
+    source: PathBuf,
+    target: &'static str,
+    read_only: bool,
+}
+
+impl BindMount {
+    fn new(source: PathBuf, target: &'static str, read_only: bool) -> Self {
+        Self { source, target, read_only }
+    }
+}
 
 /// Manages the NGINX proxy container
 pub struct ContainerManager {
@@ -22,9 +92,14 @@ pub struct ContainerManager {
     container_name: String,
     image: String,
     base_dir: PathBuf,
-    volume_mounts: Vec<String>,
+    volume_mounts: Vec<BindMount>,
     restart_policy: RestartPolicyNameEnum,
     network_name: String,
+    use_host_network: bool,
+    replace_existing: bool,
+    config_delivery: ConfigDelivery,
+    registry_auth: Option<RegistryAuth>,
+    dns: Vec<IpAddr>,
 }
 
 impl ContainerManager {
@@ -32,28 +107,227 @@ impl ContainerManager {
     pub fn new(docker: Docker) -> Self {
         let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
 
-        let data_dir = crate::installer::get_data_dir();
-        let certs_dir = crate::installer::get_certs_dir();
-        let nginx_log_dir = crate::installer::get_nginx_log_dir();
-
-        let nginx_config_mount = format!(
-            "{}:/etc/nginx/nginx.conf:ro",
-            data_dir.join("nginx.conf").to_str().unwrap()
-        );
+        let config_delivery = crate::config::get().config_delivery;
+        let certs_mount = BindMount::new(crate::installer::get_certs_dir(), "/etc/ssl/certs", true);
+        let log_mount = BindMount::new(crate::installer::get_nginx_log_dir(), "/var/log/nginx", false);
+        let cache_mount = BindMount::new(crate::installer::get_nginx_cache_dir(), NGINX_CACHE_MOUNT_DIR, false);
 
-        let certs_mount = format!("{}:/etc/ssl/certs:ro", certs_dir.to_str().unwrap());
-
-        let log_mount = format!("{}:/var/log/nginx", nginx_log_dir.to_str().unwrap());
+        // In copy mode the config is uploaded into the container instead of mounted
+        let mut volume_mounts = vec![certs_mount, log_mount, cache_mount];
+        if config_delivery == ConfigDelivery::Mount {
+            let nginx_config_mount = BindMount::new(crate::installer::get_nginx_config_dir(), NGINX_CONFIG_MOUNT_DIR, true);
+            volume_mounts.insert(0, nginx_config_mount);
+        }
 
         Self {
             docker,
-            label: String::from("kz.byte0.autolocalhost.managed-nginx-container"),
-            container_name: String::from("autolocalhost-nginx-container"),
-            image: String::from("nginx:latest"),
+            label: crate::config::label(NGINX_CONTAINER_LABEL),
+            container_name: crate::config::resource_name("autolocalhost-nginx-container"),
+            image: crate::config::get().nginx_image.clone(),
             base_dir: current_dir,
-            volume_mounts: vec![nginx_config_mount, certs_mount, log_mount],
+            volume_mounts,
             restart_policy: RestartPolicyNameEnum::UNLESS_STOPPED,
-            network_name: String::from("autolocalhost-external-network"),
+            network_name: crate::config::resource_name("autolocalhost-external-network"),
+            use_host_network: crate::config::get().use_host_network,
+            replace_existing: crate::config::get().replace_existing_nginx,
+            config_delivery,
+            registry_auth: crate::config::get().registry_auth.clone(),
+            dns: crate::config::get().nginx_dns.clone(),
+        }
+    }
+
+    /// Name of the managed NGINX container
+    pub fn container_name(&self) -> &str {
+        &self.container_name
+    }
+
+    /// Image used for the NGINX container
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    /// Network that proxied containers join so NGINX can reach them by name
+    pub fn network_name(&self) -> &str {
+        &self.network_name
+    }
+
+    /// Names of all containers attached to the managed network, NGINX itself included
+    pub async fn list_containers_in_network(&self) -> Result<Vec<String>> {
+        let network = self.docker
+            .inspect_network(&self.network_name, None::<InspectNetworkOptions<String>>)
+            .await?;
+
+        let mut names: Vec<String> = network.containers
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, container)| container.name.unwrap_or(id))
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Remove the managed network unless containers are still attached to it
+    pub async fn remove_network(&self) -> Result<NetworkRemoval> {
+        let attached = match self.list_containers_in_network().await {
+            Ok(names) => names,
+            Err(e) if matches!(
+                e.downcast_ref::<BollardError>(),
+                Some(BollardError::DockerResponseServerError { status_code: 404, .. })
+            ) => return Ok(NetworkRemoval::NotFound),
+            Err(e) => return Err(e),
+        };
+
+        if !attached.is_empty() {
+            return Ok(NetworkRemoval::InUse(attached));
+        }
+
+        self.docker
+            .remove_network(&self.network_name)
+            .await
+            .map_err(|e| anyhow!("Failed to remove network {}: {}", self.network_name, e))?;
+
+        info!("Removed network {}", self.network_name);
+        Ok(NetworkRemoval::Removed)
+    }
+
+    /// Apply a regenerated config: reload it in the running container when the container topology
+    /// (published ports, network, config delivery, DNS servers, image, mounts, label) matches the one
+    /// recorded when the container was created, otherwise recreate the container and record the new topology
+    pub async fn apply(&self, ports: &[u16]) -> Result<UpdateStrategy> {
+        let topology = self.topology(ports).key();
+        let topology_path = crate::installer::get_data_dir().join(TOPOLOGY_FILE_NAME);
+        let applied = tokio::fs::read_to_string(&topology_path).await.ok();
+
+        if choose_strategy(applied.as_deref(), &topology, self.is_running().await) == UpdateStrategy::Reload {
+            // A recreated container would not start with a rejected config either, so the running
+            // container keeps serving the previous one
+            match self.check_config().await {
+                Ok(true) => match self.signal_reload().await {
+                    Ok(()) => {
+                        info!("Reloaded the configuration of NGINX container {}", self.container_name);
+                        return Ok(UpdateStrategy::Reload);
+                    }
+                    Err(e) => warn!("Failed to reload NGINX, recreating the container: {}", e),
+                },
+                Ok(false) if self.running_image().await.as_deref() == Some(self.image.as_str()) => {
+                    return Err(config_rejected(&self.container_name));
+                }
+                // The recorded topology is stale, e.g. written before the image was part of it
+                Ok(false) => warn!(
+                    "NGINX container {} does not run {}, recreating it with the new configuration",
+                    self.container_name, self.image
+                ),
+                Err(e) => warn!("Failed to check the NGINX config, recreating the container: {}", e),
+            }
+        } else {
+            debug!("NGINX container topology changed, recreating the container");
+        }
+
+        // Forget the old topology first, so a failed recreate is never followed by a reload
+        let _ = tokio::fs::remove_file(&topology_path).await;
+        self.create_and_start(ports).await?;
+
+        if let Err(e) = tokio::fs::write(&topology_path, &topology).await {
+            warn!("Failed to record the NGINX container topology in {}: {}", topology_path.display(), e);
+        }
+        Ok(UpdateStrategy::Recreate)
+    }
+
+    /// Make the running NGINX master process re-read its config (SIGHUP) once `nginx -t` accepts it
+    pub async fn reload(&self) -> Result<()> {
+        if !self.check_config().await? {
+            return Err(config_rejected(&self.container_name));
+        }
+
+        self.signal_reload().await
+    }
+
+    /// Deliver the generated config to the running container and test it with `nginx -t`.
+    /// Returns false, after logging NGINX's output and module hints, when NGINX rejects it.
+    async fn check_config(&self) -> Result<bool> {
+        if self.config_delivery == ConfigDelivery::Copy {
+            self.upload_config(&self.container_name).await?;
+        }
+
+        let config_path = format!("{}/nginx.conf", NGINX_CONFIG_MOUNT_DIR);
+        let options = CreateExecOptions {
+            cmd: Some(vec!["nginx", "-t", "-c", config_path.as_str()]),
+            attach_stdout: Some(true),
+            attach_stderr: Some(true),
+            ..Default::default()
+        };
+        let exec = self.docker.create_exec(&self.container_name, options).await
+            .map_err(|e| anyhow!("Failed to run nginx -t in {}: {}", self.container_name, e))?;
+
+        let mut lines = Vec::new();
+        if let StartExecResults::Attached { mut output, .. } = self.docker.start_exec(&exec.id, None).await? {
+            while let Some(chunk) = output.next().await {
+                lines.extend(chunk?.to_string().lines().map(str::to_string));
+            }
+        }
+
+        let exit_code = self.docker.inspect_exec(&exec.id).await?.exit_code;
+        if exit_code == Some(0) {
+            return Ok(true);
+        }
+
+        for line in &lines {
+            error!("nginx: {}", line);
+        }
+        for hint in module_hints(&lines, &self.image) {
+            error!("{}", hint);
+        }
+        Ok(false)
+    }
+
+    async fn signal_reload(&self) -> Result<()> {
+        self.docker
+            .kill_container(&self.container_name, Some(KillContainerOptions { signal: "HUP" }))
+            .await
+            .map_err(|e| anyhow!("Failed to signal NGINX container {}: {}", self.container_name, e))
+    }
+
+    fn topology<'a>(&'a self, ports: &'a [u16]) -> Topology<'a> {
+        Topology {
+            ports,
+            network_mode: self.network_mode(),
+            delivery: self.config_delivery,
+            dns: &self.dns,
+            image: &self.image,
+            mounts: &self.volume_mounts,
+            network_ipv6: crate::config::get().network_ipv6,
+            label: &self.label,
+        }
+    }
+
+    /// Image the existing NGINX container was created from
+    async fn running_image(&self) -> Option<String> {
+        self.docker.inspect_container(&self.container_name, None).await
+            .ok()
+            .and_then(|details| details.config)
+            .and_then(|config| config.image)
+    }
+
+    /// Whether our NGINX container exists and is running
+    pub async fn is_running(&self) -> bool {
+        match self.docker.inspect_container(&self.container_name, None).await {
+            Ok(details) => {
+                let managed = details.config
+                    .and_then(|config| config.labels)
+                    .map(|labels| labels.contains_key(&self.label))
+                    .unwrap_or(false);
+                managed && details.state.and_then(|state| state.running).unwrap_or(false)
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn network_mode(&self) -> String {
+        if self.use_host_network {
+            String::from("host")
+        } else {
+            self.network_name.clone()
         }
     }
 
@@ -65,29 +339,69 @@ impl ContainerManager {
         // Stop and remove existing containers
         self.stop_and_remove().await?;
 
+        // Make sure no unmanaged container is holding our name
+        self.resolve_name_conflict().await?;
+
+        // With our own container gone, anything still holding a port would make NGINX fail to bind it
+        let mappings: Vec<PortMapping> = ports.iter().map(|&port| PortMapping::new(port, port)).collect();
+        for port in check_port_conflicts(&mappings).await {
+            warn!("Port {} is already in use on the host; NGINX will not be able to listen on it", port);
+        }
+
         debug!("Creating NGINX container with {} ports", ports.len());
 
+        if crate::config::get().server_header.is_some() {
+            warn!(
+                "AUTOLOCALHOST_SERVER_HEADER needs the headers-more module, which {} does not include; \
+                 NGINX will fail to start unless the image provides modules/ngx_http_headers_more_filter_module.so",
+                self.image
+            );
+        }
+
         // Format ports for Docker API
         let mut port_bindings = HashMap::new();
         let mut exposed_ports = HashMap::new();
 
-        for port in ports {
-            let port_key = format!("{}/tcp", port);
-            exposed_ports.insert(port_key.clone(), HashMap::new());
+        // Host networking binds NGINX directly to host ports, so no port publishing is needed
+        if !self.use_host_network {
+            for port in ports {
+                let port_key = format!("{}/tcp", port);
+                exposed_ports.insert(port_key.clone(), HashMap::new());
 
-            let host_binding = vec![PortBinding {
-                host_ip: Some(String::from("")),
-                host_port: Some(port.to_string()),
-            }];
+                let host_binding = vec![PortBinding {
+                    host_ip: Some(String::from("")),
+                    host_port: Some(port.to_string()),
+                }];
 
-            port_bindings.insert(port_key, Some(host_binding));
+                port_bindings.insert(port_key, Some(host_binding));
+            }
         }
 
         // Ensure the network exists
-        self.ensure_network_exists().await?;
+        let network_mode = if self.use_host_network {
+            warn!(
+                "NGINX container uses host network mode: container names are not resolvable, \
+                 so proxy_pass targets must use an explicit IP and port"
+            );
+            String::from("host")
+        } else {
+            self.ensure_network_exists().await?;
+            self.network_name.clone()
+        };
 
         // Format mount points for Docker API
-        let mounts = self.prepare_mounts()?;
+        let mounts = prepare_mounts(&self.volume_mounts, &self.base_dir);
+
+        // Docker rejects custom DNS servers together with host networking
+        let dns = if self.dns.is_empty() {
+            None
+        } else if self.use_host_network {
+            warn!("AUTOLOCALHOST_NGINX_DNS is ignored with host networking, NGINX uses the host's resolvers");
+            None
+        } else {
+            debug!("NGINX container resolves names through {:?}", self.dns);
+            Some(self.dns.iter().map(|server| server.to_string()).collect())
+        };
 
         // Create labels for container
         let mut labels = HashMap::new();
@@ -97,17 +411,26 @@ impl ContainerManager {
         let host_config = HostConfig {
             port_bindings: Some(port_bindings),
             restart_policy: Some(RestartPolicy {
-                name: Some(self.restart_policy.clone()),
+                name: Some(self.restart_policy),
                 maximum_retry_count: None,
             }),
             mounts: Some(mounts),
-            network_mode: Some(self.network_name.clone()),
+            network_mode: Some(network_mode),
+            dns,
             ..Default::default()
         };
 
         // Create container config
+        // The config directory is mounted rather than the file itself, so point NGINX at it
         let container_config = Config {
             image: Some(self.image.clone()),
+            cmd: Some(vec![
+                String::from("nginx"),
+                String::from("-c"),
+                format!("{}/nginx.conf", NGINX_CONFIG_MOUNT_DIR),
+                String::from("-g"),
+                String::from("daemon off;"),
+            ]),
             exposed_ports: Some(exposed_ports),
             host_config: Some(host_config),
             labels: Some(labels),
@@ -133,10 +456,14 @@ impl ContainerManager {
             }
         }
 
+        // The container is recreated on every update, so the config is in place before NGINX starts
+        if self.config_delivery == ConfigDelivery::Copy {
+            self.upload_config(&response.id).await?;
+        }
+
         // Start the container
-        self.docker
-            .start_container(&response.id, None::<StartContainerOptions<String>>)
-            .await?;
+        self.start_with_retry(&response.id).await?;
+        self.check_still_running(&response.id).await?;
 
         info!(
             "NGINX container {} started with ID: {}",
@@ -145,6 +472,121 @@ impl ContainerManager {
         Ok(())
     }
 
+    /// Start the created container, retrying transient failures with backoff. When every
+    /// attempt fails, the container's state and logs are logged before returning the error.
+    async fn start_with_retry(&self, container_id: &str) -> Result<()> {
+        let mut delay = Duration::from_millis(START_RETRY_DELAY_MS);
+        let mut attempt = 1;
+
+        loop {
+            match self.docker.start_container(container_id, None::<StartContainerOptions<String>>).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < START_ATTEMPTS => {
+                    warn!(
+                        "Failed to start NGINX container (attempt {}/{}): {}; retrying in {}ms",
+                        attempt, START_ATTEMPTS, e, delay.as_millis()
+                    );
+                    sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    let logs = self.log_start_failure(container_id).await;
+                    for hint in module_hints(&logs, &self.image) {
+                        error!("{}", hint);
+                    }
+                    return Err(anyhow!("Failed to start NGINX container after {} attempts: {}", START_ATTEMPTS, e));
+                }
+            }
+        }
+    }
+
+    /// NGINX exits right after starting when it rejects its config, which the start call
+    /// itself does not report. Point out directives of modules missing from the image.
+    async fn check_still_running(&self, container_id: &str) -> Result<()> {
+        sleep(Duration::from_millis(STARTUP_CHECK_DELAY_MS)).await;
+
+        let running = self.docker.inspect_container(container_id, None).await
+            .ok()
+            .and_then(|details| details.state)
+            .and_then(|state| state.running)
+            .unwrap_or(false);
+        if running {
+            return Ok(());
+        }
+
+        let logs = self.log_start_failure(container_id).await;
+        for hint in module_hints(&logs, &self.image) {
+            error!("{}", hint);
+        }
+        Err(anyhow!("NGINX container {} exited right after starting, see its log above", self.container_name))
+    }
+
+    /// Log the state and last output of a container that failed to start, returning the output lines
+    async fn log_start_failure(&self, container_id: &str) -> Vec<String> {
+        match self.docker.inspect_container(container_id, None).await {
+            Ok(details) => {
+                if let Some(state) = details.state {
+                    error!(
+                        "NGINX container state: {} (exit code {}){}",
+                        state.status.map(|status| status.to_string()).unwrap_or_else(|| String::from("unknown")),
+                        state.exit_code.unwrap_or_default(),
+                        state.error.filter(|error| !error.is_empty()).map(|error| format!(", error: {}", error)).unwrap_or_default()
+                    );
+                }
+            }
+            Err(e) => warn!("Failed to inspect NGINX container {}: {}", container_id, e),
+        }
+
+        let options = LogsOptions::<String> {
+            stdout: true,
+            stderr: true,
+            tail: String::from(START_FAILURE_LOG_LINES),
+            ..Default::default()
+        };
+        let mut logs = self.docker.logs(container_id, Some(options));
+        let mut lines = Vec::new();
+        while let Some(line) = logs.next().await {
+            match line {
+                Ok(output) => {
+                    let line = output.to_string().trim_end().to_string();
+                    error!("nginx: {}", line);
+                    lines.push(line);
+                }
+                Err(e) => {
+                    warn!("Failed to read NGINX container logs: {}", e);
+                    break;
+                }
+            }
+        }
+        lines
+    }
+
+    /// Copy the generated nginx.conf into a created container as a tar archive
+    async fn upload_config(&self, container_id: &str) -> Result<()> {
+        let config_path = crate::installer::get_nginx_config_dir().join("nginx.conf");
+        let config = tokio::fs::read(&config_path)
+            .await
+            .map_err(|e| anyhow!("Failed to read {}: {}", config_path.display(), e))?;
+
+        let (parent_dir, config_dir) = NGINX_CONFIG_MOUNT_DIR
+            .rsplit_once('/')
+            .ok_or_else(|| anyhow!("Invalid config directory {}", NGINX_CONFIG_MOUNT_DIR))?;
+        let archive = tar_archive::directory_with_files(config_dir, &[("nginx.conf", &config)])?;
+
+        let options = UploadToContainerOptions {
+            path: parent_dir,
+            ..Default::default()
+        };
+        self.docker
+            .upload_to_container(container_id, Some(options), archive.into())
+            .await
+            .map_err(|e| anyhow!("Failed to copy the NGINX config into the container: {}", e))?;
+
+        debug!("Copied {} into the NGINX container", config_path.display());
+        Ok(())
+    }
+
     /// Stop and remove existing managed NGINX containers
     pub async fn stop_and_remove(&self) -> Result<usize> {
         debug!("Stopping and removing existing NGINX containers");
@@ -179,8 +621,12 @@ impl ContainerManager {
                 // Stop container if running
                 if container.state == Some(String::from("running")) {
                     info!("Stopping container: {}", container_name);
-                    if let Err(e) = self.docker.stop_container(&id, None).await {
-                        warn!("Error stopping container {}: {}", id, e);
+                    match self.docker.stop_container(&id, None).await {
+                        Ok(()) => {}
+                        Err(BollardError::DockerResponseServerError { status_code: 404, .. }) => {
+                            debug!("Container {} was already removed before it could be stopped", id);
+                        }
+                        Err(e) => warn!("Error stopping container {}: {}", id, e),
                     }
                 }
 
@@ -191,14 +637,16 @@ impl ContainerManager {
                     ..Default::default()
                 };
 
-                if let Err(e) = self
+                match self
                     .docker
                     .remove_container(&id, Some(remove_options))
                     .await
                 {
-                    warn!("Error removing container {}: {}", id, e);
-                } else {
-                    count += 1;
+                    Ok(()) => count += 1,
+                    Err(BollardError::DockerResponseServerError { status_code: 404, .. }) => {
+                        debug!("Container {} was already removed", id);
+                    }
+                    Err(e) => warn!("Error removing container {}: {}", id, e),
                 }
             }
         }
@@ -207,6 +655,47 @@ impl ContainerManager {
         Ok(count)
     }
 
+    /// Handle a container that uses our name but lacks the managed label (e.g. created manually)
+    async fn resolve_name_conflict(&self) -> Result<()> {
+        let details = match self.docker.inspect_container(&self.container_name, None).await {
+            Ok(details) => details,
+            Err(BollardError::DockerResponseServerError { status_code: 404, .. }) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let is_managed = details
+            .config
+            .and_then(|config| config.labels)
+            .map(|labels| labels.contains_key(&self.label))
+            .unwrap_or(false);
+
+        if is_managed {
+            return Ok(());
+        }
+
+        if !self.replace_existing {
+            return Err(anyhow!(
+                "A container named {} already exists but is not managed by autolocalhost. \
+                 Remove it manually or set AUTOLOCALHOST_REPLACE_EXISTING=true to replace it",
+                self.container_name
+            ));
+        }
+
+        warn!(
+            "Replacing unmanaged container {} that occupies the NGINX container name",
+            self.container_name
+        );
+        let remove_options = RemoveContainerOptions {
+            force: true,
+            ..Default::default()
+        };
+        self.docker
+            .remove_container(&self.container_name, Some(remove_options))
+            .await?;
+
+        Ok(())
+    }
+
     /// Ensure the network exists
     async fn ensure_network_exists(&self) -> Result<()> {
         // List networks
@@ -215,24 +704,48 @@ impl ContainerManager {
             .list_networks(None::<ListNetworksOptions<String>>)
             .await?;
 
+        let ipv6 = crate::config::get().network_ipv6;
+
         // Check if our network already exists
         for network in networks {
             if network.name == Some(self.network_name.clone()) {
                 debug!("Network {} already exists", self.network_name);
+                if ipv6 && network.enable_ipv6 != Some(true) {
+                    warn!(
+                        "AUTOLOCALHOST_NETWORK_IPV6 is set but network {} was created without IPv6; \
+                         remove it (docker network rm {}) to have it recreated dual-stack",
+                        self.network_name, self.network_name
+                    );
+                }
                 return Ok(());
             }
         }
 
         // Create the network
-        info!("Creating network: {}", self.network_name);
+        info!("Creating network: {}{}", self.network_name, if ipv6 { " (dual-stack)" } else { "" });
 
         let mut network_labels = HashMap::new();
         network_labels.insert(self.label.clone(), String::from("true"));
 
+        // Docker keeps allocating the IPv4 subnet itself; IPv6 needs an explicit subnet
+        let ipam = if ipv6 {
+            Ipam {
+                config: Some(vec![IpamConfig {
+                    subnet: Some(String::from(NETWORK_IPV6_SUBNET)),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }
+        } else {
+            Ipam::default()
+        };
+
         let options = CreateNetworkOptions {
             name: self.network_name.clone(),
             driver: String::from("bridge"),
             labels: network_labels,
+            enable_ipv6: ipv6,
+            ipam,
             ..Default::default()
         };
 
@@ -269,7 +782,21 @@ impl ContainerManager {
         }
 
         // Pull the image
-        info!("Pulling image: {}", self.image);
+        let credentials = match &self.registry_auth {
+            Some(auth) => {
+                info!("Pulling image: {} as {}", self.image, auth);
+                Some(DockerCredentials {
+                    username: Some(auth.username.clone()),
+                    password: Some(auth.password.clone()),
+                    serveraddress: Some(auth.server_address.clone()).filter(|address| !address.is_empty()),
+                    ..Default::default()
+                })
+            }
+            None => {
+                info!("Pulling image: {}", self.image);
+                None
+            }
+        };
 
         let pull_options = CreateImageOptions {
             from_image: image_name,
@@ -277,13 +804,21 @@ impl ContainerManager {
             ..Default::default()
         };
 
-        let mut stream = self.docker.create_image(Some(pull_options), None, None);
+        let mut stream = self.docker.create_image(Some(pull_options), None, credentials);
+        let mut progress = PullProgress::default();
+        let mut last_report = Instant::now();
 
         while let Some(result) = stream.next().await {
             match result {
                 Ok(info) => {
-                    if let Some(status) = info.status {
-                        debug!("Pull status: {}", status);
+                    if let Some(status) = &info.status {
+                        debug!("Pull status: {}{}", info.id.as_deref().map(|id| format!("{}: ", id)).unwrap_or_default(), status);
+                        progress.update(info.id.as_deref(), status);
+                    }
+
+                    if progress.has_layers() && last_report.elapsed() >= Duration::from_secs(PULL_PROGRESS_INTERVAL_SECS) {
+                        info!("Pulling {}: {}", self.image, progress.summary());
+                        last_report = Instant::now();
                     }
                 }
                 Err(e) => {
@@ -293,40 +828,266 @@ impl ContainerManager {
         }
 
         info!("Successfully pulled image: {}", self.image);
-        Ok(())
-    }
 
-    /// Prepare mount points for Docker API
-    fn prepare_mounts(&self) -> Result<Vec<Mount>> {
-        let mut mounts = Vec::new();
+        // Log the resolved digest so users can pin the exact image they got
+        match self.image_digest().await {
+            Ok(Some(digest)) => info!("Image {} resolved to {}", self.image, digest),
+            Ok(None) => info!("Image {} has no repository digest", self.image),
+            Err(e) => warn!("Failed to inspect pulled image {}: {}", self.image, e),
+        }
 
-        for mount_str in &self.volume_mounts {
-            let parts: Vec<&str> = mount_str.split(':').collect();
+        Ok(())
+    }
 
-            if parts.len() < 2 {
-                return Err(anyhow!("Invalid mount format: {}", mount_str));
-            }
+    /// Repository digest of the local NGINX image (e.g. "nginx@sha256:...")
+    pub async fn image_digest(&self) -> Result<Option<String>> {
+        let details = self.docker.inspect_image(&self.image).await?;
 
-            let source = parts[0];
-            let target = parts[1];
-            let readonly = parts.get(2) == Some(&"ro");
+        Ok(details
+            .repo_digests
+            .and_then(|digests| digests.into_iter().next()))
+    }
+}
 
+/// Prepare mount points for Docker API
+fn prepare_mounts(volume_mounts: &[BindMount], base_dir: &Path) -> Vec<Mount> {
+    volume_mounts
+        .iter()
+        .map(|mount| {
             // Convert relative paths to absolute
-            let source_path = if Path::new(source).is_absolute() {
-                PathBuf::from(source)
+            let source_path = if mount.source.is_absolute() {
+                mount.source.clone()
             } else {
-                self.base_dir.join(source)
+                base_dir.join(&mount.source)
             };
 
-            mounts.push(Mount {
-                target: Some(target.to_string()),
+            Mount {
+                target: Some(mount.target.to_string()),
                 source: Some(source_path.to_string_lossy().to_string()),
                 typ: Some(MountTypeEnum::BIND),
-                read_only: Some(readonly),
+                read_only: Some(mount.read_only),
                 ..Default::default()
-            });
+            }
+        })
+        .collect()
+}
+
+/// State of the layers of an image pull, aggregated from the pull status stream
+#[derive(Debug, Default)]
+struct PullProgress {
+    layers: HashMap<String, LayerState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayerState {
+    Waiting,
+    Downloading,
+    Extracting,
+    Complete,
+}
+
+impl PullProgress {
+    /// Record a status line; lines that are not about a layer (e.g. "Pulling from library/nginx") are ignored
+    fn update(&mut self, id: Option<&str>, status: &str) {
+        let Some(id) = id else {
+            return;
+        };
+
+        let state = match status {
+            "Pulling fs layer" | "Waiting" => LayerState::Waiting,
+            "Downloading" | "Verifying Checksum" | "Download complete" => LayerState::Downloading,
+            "Extracting" => LayerState::Extracting,
+            "Pull complete" | "Already exists" => LayerState::Complete,
+            _ => return,
+        };
+        self.layers.insert(id.to_string(), state);
+    }
+
+    fn has_layers(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// e.g. "3/7 layers (2 downloading, 1 extracting)"
+    fn summary(&self) -> String {
+        let count = |state| self.layers.values().filter(|&&s| s == state).count();
+
+        let mut summary = format!("{}/{} layers", count(LayerState::Complete), self.layers.len());
+        let active: Vec<String> = [(LayerState::Downloading, "downloading"), (LayerState::Extracting, "extracting")]
+            .iter()
+            .map(|&(state, label)| (count(state), label))
+            .filter(|&(n, _)| n > 0)
+            .map(|(n, label)| format!("{} {}", n, label))
+            .collect();
+        if !active.is_empty() {
+            summary.push_str(&format!(" ({})", active.join(", ")));
+        }
+        summary
+    }
+}
+
+/// What a config reload cannot change in the NGINX container
+struct Topology<'a> {
+    ports: &'a [u16],
+    network_mode: String,
+    delivery: ConfigDelivery,
+    dns: &'a [IpAddr],
+    image: &'a str,
+    mounts: &'a [BindMount],
+    network_ipv6: bool,
+    label: &'a str,
+}
+
+impl Topology<'_> {
+    /// Canonical description of the topology, recorded when the container is created
+    fn key(&self) -> String {
+        let mut ports = self.ports.to_vec();
+        ports.sort_unstable();
+        ports.dedup();
+
+        let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+        let dns: Vec<String> = self.dns.iter().map(|server| server.to_string()).collect();
+        let mut key = format!(
+            "ports={}\nnetwork={}\nipv6={}\ndelivery={:?}\ndns={}\nimage={}\nlabel={}\n",
+            ports.join(","), self.network_mode, self.network_ipv6, self.delivery, dns.join(","), self.image, self.label
+        );
+        for mount in self.mounts {
+            let mode = if mount.read_only { "ro" } else { "rw" };
+            key.push_str(&format!("mount={}:{}:{}\n", mount.source.display(), mount.target, mode));
+        }
+        key
+    }
+}
+
+/// Hints for `unknown directive "..."` errors in NGINX output caused by modules the image lacks
+fn module_hints(logs: &[String], image: &str) -> Vec<String> {
+    let mut hints = Vec::new();
+
+    for line in logs {
+        let Some((_, rest)) = line.split_once("unknown directive \"") else {
+            continue;
+        };
+        let Some((directive, _)) = rest.split_once('"') else {
+            continue;
+        };
+
+        let module = MODULE_DIRECTIVES
+            .iter()
+            .find(|(pattern, _)| directive.contains(pattern))
+            .map(|(_, module)| format!("the {} module", module))
+            .unwrap_or_else(|| String::from("a module"));
+        let hint = format!(
+            "Directive \"{}\" requires {} that is not in {}; set AUTOLOCALHOST_NGINX_IMAGE to an image that includes it",
+            directive, module, image
+        );
+        if !hints.contains(&hint) {
+            hints.push(hint);
         }
+    }
+
+    hints
+}
+
+fn config_rejected(container_name: &str) -> anyhow::Error {
+    anyhow!("NGINX rejected the new configuration, container {} keeps running the previous one", container_name)
+}
+
+/// Reload only when the running container was created with the same topology
+fn choose_strategy(applied: Option<&str>, topology: &str, running: bool) -> UpdateStrategy {
+    if running && applied == Some(topology) {
+        UpdateStrategy::Reload
+    } else {
+        UpdateStrategy::Recreate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_mount_sources_with_spaces_and_colons_intact() {
+        let mounts = vec![
+            BindMount::new(PathBuf::from("/var/lib/auto localhost/nginx"), NGINX_CONFIG_MOUNT_DIR, true),
+            BindMount::new(PathBuf::from("certs: local"), "/etc/ssl/certs", false),
+        ];
+
+        let mounts = prepare_mounts(&mounts, Path::new("/opt/my tools"));
+
+        assert_eq!(mounts[0].source.as_deref(), Some("/var/lib/auto localhost/nginx"));
+        assert_eq!(mounts[0].target.as_deref(), Some(NGINX_CONFIG_MOUNT_DIR));
+        assert_eq!(mounts[0].read_only, Some(true));
+        assert_eq!(mounts[1].source.as_deref(), Some("/opt/my tools/certs: local"));
+        assert_eq!(mounts[1].target.as_deref(), Some("/etc/ssl/certs"));
+        assert_eq!(mounts[1].read_only, Some(false));
+    }
+
+    #[test]
+    fn reloads_only_when_the_container_topology_is_unchanged() {
+        let mounts = [BindMount::new(PathBuf::from("/var/lib/autolocalhost/certs"), "/etc/ssl/certs", true)];
+        let topology = |ports: &'static [u16]| Topology {
+            ports,
+            network_mode: String::from("autolocalhost-external-network"),
+            delivery: ConfigDelivery::Mount,
+            dns: &[],
+            image: "nginx:latest",
+            mounts: &mounts,
+            network_ipv6: false,
+            label: "kz.byte0.autolocalhost.managed-nginx-container",
+        };
+        let applied = topology(&[443, 80]).key();
+
+        // A new domain on the same ports, listed in another order
+        let same_ports = topology(&[80, 443, 80]).key();
+        assert_eq!(choose_strategy(Some(&applied), &same_ports, true), UpdateStrategy::Reload);
+        assert_eq!(choose_strategy(Some(&applied), &same_ports, false), UpdateStrategy::Recreate);
+        assert_eq!(choose_strategy(None, &same_ports, true), UpdateStrategy::Recreate);
+
+        let new_port = topology(&[80, 443, 8443]).key();
+        assert_eq!(choose_strategy(Some(&applied), &new_port, true), UpdateStrategy::Recreate);
+
+        let host_network = Topology { network_mode: String::from("host"), ..topology(&[80, 443]) }.key();
+        assert_eq!(choose_strategy(Some(&applied), &host_network, true), UpdateStrategy::Recreate);
+
+        let openresty = Topology { image: "openresty/openresty", ..topology(&[80, 443]) }.key();
+        assert_eq!(choose_strategy(Some(&applied), &openresty, true), UpdateStrategy::Recreate);
+
+        let moved_certs = [BindMount::new(PathBuf::from("/srv/certs"), "/etc/ssl/certs", true)];
+        let moved = Topology { mounts: &moved_certs, ..topology(&[80, 443]) }.key();
+        assert_eq!(choose_strategy(Some(&applied), &moved, true), UpdateStrategy::Recreate);
+    }
+
+    #[test]
+    fn summarizes_image_pull_progress_per_layer() {
+        let mut progress = PullProgress::default();
+        progress.update(Some("latest"), "Pulling from library/nginx");
+        for layer in ["a", "b", "c", "d"] {
+            progress.update(Some(layer), "Pulling fs layer");
+        }
+        progress.update(Some("a"), "Already exists");
+        progress.update(Some("b"), "Downloading");
+        progress.update(Some("c"), "Download complete");
+        progress.update(Some("c"), "Extracting");
+        progress.update(None, "Digest: sha256:0123");
+
+        assert_eq!(progress.summary(), "1/4 layers (1 downloading, 1 extracting)");
+
+        for layer in ["b", "c", "d"] {
+            progress.update(Some(layer), "Pull complete");
+        }
+        assert_eq!(progress.summary(), "4/4 layers");
+    }
+
+    #[test]
+    fn hints_at_modules_missing_from_the_image() {
+        let logs = vec![
+            String::from("2024/01/01 00:00:00 [emerg] 1#1: unknown directive \"more_set_headers\" in /etc/nginx/autolocalhost/nginx.conf:21"),
+            String::from("nginx: [emerg] unknown directive \"more_set_headers\" in /etc/nginx/autolocalhost/nginx.conf:21"),
+            String::from("nginx: [emerg] unknown directive \"foo_bar\" in /etc/nginx/autolocalhost/nginx.conf:30"),
+        ];
 
-        Ok(mounts)
+        let hints = module_hints(&logs, "nginx:latest");
+        assert_eq!(hints.len(), 2);
+        assert!(hints[0].starts_with("Directive \"more_set_headers\" requires the headers-more module that is not in nginx:latest"));
+        assert!(hints[1].starts_with("Directive \"foo_bar\" requires a module"));
     }
 }
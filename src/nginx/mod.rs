@@ -1,2 +1,4 @@
 pub mod config_generator;
+pub mod config_linter;
 pub mod container_manager;
+pub mod template_helpers;
@@ -0,0 +1,69 @@
+use handlebars::{handlebars_helper, Handlebars, JsonValue};
+
+// Case conversion; non-string values are passed through unchanged
+handlebars_helper!(lower: |value: Json| match value.as_str() {
+    Some(s) => JsonValue::from(s.to_lowercase()),
+    None => value.clone(),
+});
+
+handlebars_helper!(upper: |value: Json| match value.as_str() {
+    Some(s) => JsonValue::from(s.to_uppercase()),
+    None => value.clone(),
+});
+
+// `{{default x "fallback"}}`: the fallback when x is missing, null, false or empty
+handlebars_helper!(default: |value: Json, fallback: Json| {
+    let is_empty = match value {
+        JsonValue::Null | JsonValue::Bool(false) => true,
+        JsonValue::String(s) => s.is_empty(),
+        JsonValue::Array(items) => items.is_empty(),
+        _ => false,
+    };
+
+    if is_empty { fallback.clone() } else { value.clone() }
+});
+
+// `{{port_range start=8080 count=3}}`: consecutive ports joined with commas, `8080,8081,8082`
+handlebars_helper!(port_range: |{start: u64 = 0, count: u64 = 0}| {
+    (start..=u64::from(u16::MAX))
+        .take(count as usize)
+        .map(|port| port.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+});
+
+// `{{domain_to_upstream domain}}`: upstream block name for a domain, `app.local` becomes `app_local_upstream`
+handlebars_helper!(domain_to_upstream: |domain: str| {
+    let name: String = domain
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}_upstream", name)
+});
+
+// `{{#if (if_ssl this)}}`: whether a container has SSL ports
+handlebars_helper!(if_ssl: |container: Json| {
+    container.get("ssl_ports")
+        .and_then(JsonValue::as_array)
+        .map(|ports| !ports.is_empty())
+        .unwrap_or(false)
+});
+
+// `{{nginx_comment "text"}}`: the text as an NGINX comment, one `# ` per line
+handlebars_helper!(nginx_comment: |text: str| {
+    text.lines()
+        .map(|line| format!("# {}", line).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+});
+
+/// Register the value and structure helpers available to NGINX templates
+pub fn register(handlebars: &mut Handlebars) {
+    handlebars.register_helper("lower", Box::new(lower));
+    handlebars.register_helper("upper", Box::new(upper));
+    handlebars.register_helper("default", Box::new(default));
+    handlebars.register_helper("port_range", Box::new(port_range));
+    handlebars.register_helper("domain_to_upstream", Box::new(domain_to_upstream));
+    handlebars.register_helper("if_ssl", Box::new(if_ssl));
+    handlebars.register_helper("nginx_comment", Box::new(nginx_comment));
+}
@@ -0,0 +1,57 @@
+use anyhow::{Result, bail};
+use std::net::IpAddr;
+use crate::nginx::container_manager::ContainerManager;
+use crate::ssl::certificate_generator::CertificateGenerator;
+
+/// Replace one domain's certificate with a freshly signed one and make a running NGINX pick it up
+pub async fn run(domain: &str) -> Result<()> {
+    // The domain becomes part of the certificate file names
+    if domain.is_empty() || domain.contains(['/', '\\']) || domain.contains("..") {
+        bail!("Invalid domain: {}", domain);
+    }
+
+    let docker = crate::docker::try_connect_docker().await;
+
+    // Keep the IP SANs the service issues from the extra_ips labels of the domain's containers
+    let extra_ips = match &docker {
+        Ok(docker) => crate::docker::running_extra_ips(docker, domain).await?,
+        Err(_) => recorded_extra_ips(domain).await,
+    };
+
+    CertificateGenerator::new(domain).with_extra_ips(extra_ips).regenerate_certificates().await?;
+    // The certificate files were written as root
+    crate::installer::restore_service_ownership();
+    println!("Regenerated the certificate for {} in {}", domain, crate::installer::get_certs_dir().display());
+
+    let docker = match docker {
+        Ok(docker) => docker,
+        Err(e) => {
+            println!("Docker unavailable ({}), NGINX uses the new certificate after its next reload", e);
+            return Ok(());
+        }
+    };
+
+    let nginx_manager = ContainerManager::new(docker);
+    if nginx_manager.is_running().await {
+        nginx_manager.reload().await?;
+        println!("Reloaded NGINX container {}", nginx_manager.container_name());
+    } else {
+        println!("NGINX container is not running, the new certificate is used when it starts");
+    }
+
+    Ok(())
+}
+
+/// extra_ips of the domain's containers as of the service's last reconfiguration
+async fn recorded_extra_ips(domain: &str) -> Vec<IpAddr> {
+    match crate::state::read().await {
+        Ok(state) => state.containers.into_iter()
+            .filter(|container| container.domain == domain)
+            .flat_map(|container| container.extra_ips)
+            .collect(),
+        Err(e) => {
+            println!("No extra IP SANs known for {} ({}), the certificate only covers the defaults", domain, e);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Result, anyhow};
+use bollard::container::{Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions};
+use bollard::image::CreateImageOptions;
+use bollard::models::HostConfig;
+use bollard::Docker;
+use crate::config::label;
+use crate::nginx::container_manager::ContainerManager;
+use crate::utils::dns_resolver;
+use futures_util::StreamExt;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+const ECHO_IMAGE: &str = "hashicorp/http-echo:latest";
+const ECHO_PORT: u16 = 5678;
+const SELFTEST_DOMAIN_SUFFIX: &str = "autolocalhost-selftest.test";
+const ROUTING_TIMEOUT_SECS: u64 = 60;
+const REQUEST_TIMEOUT_SECS: u64 = 5;
+const POLL_INTERVAL_MS: u64 = 500;
+
+/// Provision a throwaway echo container and check that the running service routes
+/// its domain through NGINX end to end. Fails if any stage does not complete.
+pub async fn run() -> Result<()> {
+    let docker = crate::docker::try_connect_docker().await
+        .map_err(|e| anyhow!("Docker is not available: {}", e))?;
+    println!("[ ok ] Connected to Docker");
+
+    let nginx_manager = ContainerManager::new(docker.clone());
+    if crate::config::get().use_host_network {
+        println!("[warn] NGINX uses host networking and cannot reach containers by name; routing is expected to fail");
+    }
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let name = format!("autolocalhost-selftest-{}", &token[..8]);
+    let domain = format!("{}.{}", name, SELFTEST_DOMAIN_SUFFIX);
+
+    pull_image(&docker).await?;
+    create_echo_container(&docker, &name, &domain, &token, nginx_manager.network_name()).await?;
+
+    let result = check_routing(&docker, &name, &domain, &token).await;
+
+    let options = RemoveContainerOptions { force: true, ..Default::default() };
+    match docker.remove_container(&name, Some(options)).await {
+        Ok(()) => println!("[ ok ] Removed test container {}", name),
+        Err(e) => warn!("Failed to remove test container {}: {}", name, e),
+    }
+
+    match result {
+        Ok(()) => {
+            println!("\nSelftest PASSED: {} is routed through NGINX", domain);
+            Ok(())
+        }
+        Err(e) => {
+            println!("[fail] {}", e);
+            Err(anyhow!("Selftest FAILED, is the autolocalhost service running?"))
+        }
+    }
+}
+
+/// Start the container and wait for the hosts entry and the proxied echo response
+async fn check_routing(docker: &Docker, name: &str, domain: &str, token: &str) -> Result<()> {
+    docker.start_container(name, None::<StartContainerOptions<String>>).await
+        .map_err(|e| anyhow!("Failed to start test container {}: {}", name, e))?;
+    println!("[ ok ] Started test container {}", name);
+
+    let limit = Duration::from_secs(ROUTING_TIMEOUT_SECS);
+    let started = Instant::now();
+
+    if !dns_resolver::wait_for_hosts_entry(domain, IpAddr::V4(Ipv4Addr::LOCALHOST), limit).await {
+        return Err(anyhow!("{} was not added to the hosts file within {}s", domain, ROUTING_TIMEOUT_SECS));
+    }
+    println!("[ ok ] {} resolves to 127.0.0.1 ({:.1}s)", domain, started.elapsed().as_secs_f64());
+
+    loop {
+        match fetch(domain).await {
+            Ok(body) if body.contains(token) => break,
+            Ok(_) => debug!("{} answered without the echo response yet", domain),
+            Err(e) => debug!("Request to {} failed: {}", domain, e),
+        }
+
+        if started.elapsed() > limit {
+            return Err(anyhow!("http://{}/ did not return the echo response within {}s", domain, ROUTING_TIMEOUT_SECS));
+        }
+        sleep(Duration::from_millis(POLL_INTERVAL_MS)).await;
+    }
+    println!("[ ok ] http://{}/ returned the echo response ({:.1}s)", domain, started.elapsed().as_secs_f64());
+
+    Ok(())
+}
+
+/// Plain HTTP/1.1 GET through the proxy on port 80, returning the response body
+async fn fetch(domain: &str) -> Result<String> {
+    let request = async {
+        let mut stream = TcpStream::connect((domain, 80)).await?;
+        let request = format!("GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", domain);
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok::<_, std::io::Error>(String::from_utf8_lossy(&response).into_owned())
+    };
+
+    let response = timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS), request).await
+        .map_err(|_| anyhow!("request timed out"))??;
+
+    let (status_line, rest) = response.split_once("\r\n").unwrap_or((response.as_str(), ""));
+    if !status_line.contains(" 200 ") {
+        return Err(anyhow!("unexpected response: {}", status_line));
+    }
+
+    Ok(rest.split_once("\r\n\r\n").map(|(_, body)| body).unwrap_or("").to_string())
+}
+
+async fn pull_image(docker: &Docker) -> Result<()> {
+    let options = CreateImageOptions { from_image: ECHO_IMAGE, ..Default::default() };
+    let mut stream = docker.create_image(Some(options), None, None);
+
+    while let Some(result) = stream.next().await {
+        result.map_err(|e| anyhow!("Failed to pull image {}: {}", ECHO_IMAGE, e))?;
+    }
+
+    println!("[ ok ] Pulled {}", ECHO_IMAGE);
+    Ok(())
+}
+
+async fn create_echo_container(docker: &Docker, name: &str, domain: &str, token: &str, network: &str) -> Result<()> {
+    let mut labels = HashMap::new();
+    labels.insert(label("enabled"), String::from("true"));
+    labels.insert(label("domain"), domain.to_string());
+    labels.insert(label("ports"), format!("80:{}", ECHO_PORT));
+
+    let config = Config {
+        image: Some(String::from(ECHO_IMAGE)),
+        cmd: Some(vec![format!("-text={}", token), format!("-listen=:{}", ECHO_PORT)]),
+        labels: Some(labels),
+        host_config: Some(HostConfig {
+            network_mode: Some(network.to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let options = CreateContainerOptions { name: name.to_string(), platform: None };
+    docker.create_container(Some(options), config).await
+        .map_err(|e| anyhow!("Failed to create test container {} from {}: {}", name, ECHO_IMAGE, e))?;
+
+    println!("[ ok ] Created test container {} for {}", name, domain);
+    Ok(())
+}
@@ -1,15 +1,35 @@
 use anyhow::{anyhow, Result};
-use log::{debug, info};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use base64::Engine;
 use rcgen::{Certificate, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair, SanType};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 use time::{Duration, OffsetDateTime};
 use tokio::fs;
+use tokio::process::Command;
+use tokio::sync::{RwLock, RwLockReadGuard};
+
+/// CA certificate shared by all generators, so concurrent domain certificate
+/// generation never races to create the CA
+static CA_CACHE: Lazy<RwLock<Option<CachedCA>>> = Lazy::new(|| RwLock::new(None));
+
+/// CA certificate loaded or created for a CA directory
+struct CachedCA {
+    ca_dir: PathBuf,
+    certificate: Certificate,
+}
 
 /// Generator for SSL certificates for local domains
 pub struct CertificateGenerator {
     domain: String,
     certs_dir: PathBuf,
     ca_dir: PathBuf,
+    pkcs12_enabled: bool,
+    minimal_sans: bool,
+    /// IP SANs from the extra_ips label, added on top of 127.0.0.1
+    extra_ips: Vec<IpAddr>,
 }
 
 impl CertificateGenerator {
@@ -19,10 +39,46 @@ impl CertificateGenerator {
             domain: domain.to_string(),
             certs_dir: crate::installer::get_certs_dir(),
             ca_dir: crate::installer::get_ca_dir(),
+            pkcs12_enabled: !crate::config::get().no_pkcs12,
+            minimal_sans: crate::config::get().cert_minimal_sans,
+            extra_ips: Vec::new(),
             // certs_dir: PathBuf::from("./certs")
         }
     }
 
+    #[cfg(test)]
+    fn with_dirs(domain: &str, certs_dir: PathBuf, ca_dir: PathBuf) -> Self {
+        Self {
+            domain: domain.to_string(),
+            certs_dir,
+            ca_dir,
+            pkcs12_enabled: false,
+            minimal_sans: false,
+            extra_ips: Vec::new(),
+        }
+    }
+
+    /// Add IP SANs to the domain certificate, e.g. from the extra_ips label
+    pub fn with_extra_ips(mut self, extra_ips: Vec<IpAddr>) -> Self {
+        self.extra_ips = extra_ips;
+        self
+    }
+
+    /// IP SANs of the domain certificate: 127.0.0.1 unless SANs are minimal, then the extra IPs
+    fn ip_sans(&self) -> Vec<IpAddr> {
+        let mut ips = Vec::new();
+        if !self.minimal_sans {
+            ips.push(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+
+        for ip in &self.extra_ips {
+            if !ips.contains(ip) {
+                ips.push(*ip);
+            }
+        }
+        ips
+    }
+
     /// Create a CA certificate
     async fn create_ca_certificate(&self) -> Result<Certificate> {
         info!("Creating CA certificate");
@@ -110,21 +166,23 @@ impl CertificateGenerator {
         params
             .subject_alt_names
             .push(SanType::DnsName(self.domain.clone()));
-        params
-            .subject_alt_names
-            .push(SanType::DnsName(format!("www.{}", self.domain)));
-        params
-            .subject_alt_names
-            .push(SanType::DnsName("localhost".to_string()));
 
-        // Попробуем добавить IP-адрес 127.0.0.1
-        match "127.0.0.1".parse() {
-            Ok(ip) => {
-                params.subject_alt_names.push(SanType::IpAddress(ip));
-            }
-            Err(e) => {
-                debug!("Failed to parse IP 127.0.0.1: {}", e);
+        // Minimal SANs: the certificate is valid for exactly the domain
+        if self.minimal_sans {
+            debug!("Omitting www./localhost/127.0.0.1 SANs for {}", self.domain);
+        } else {
+            if has_www_alias(&self.domain) {
+                params
+                    .subject_alt_names
+                    .push(SanType::DnsName(format!("www.{}", self.domain)));
             }
+            params
+                .subject_alt_names
+                .push(SanType::DnsName("localhost".to_string()));
+        }
+
+        for ip in self.ip_sans() {
+            params.subject_alt_names.push(SanType::IpAddress(ip));
         }
 
         let cert = Certificate::from_params(params)?;
@@ -132,14 +190,6 @@ impl CertificateGenerator {
         Ok(cert)
     }
 
-    /// Check if CA certificate files exist
-    async fn has_ca_files(&self) -> bool {
-        let ca_cert_path = self.ca_dir.join("localCA.crt");
-        let ca_key_path = self.ca_dir.join("localCA.key");
-
-        fs::metadata(&ca_cert_path).await.is_ok() && fs::metadata(&ca_key_path).await.is_ok()
-    }
-
     /// Check if domain certificate files exist
     async fn has_domain_certs(&self) -> bool {
         let domain_cert_path = self.certs_dir.join(format!("{}.crt", self.domain));
@@ -154,11 +204,11 @@ impl CertificateGenerator {
     }
 
     /// Load CA certificate from files
-    async fn load_ca(&self) -> Result<Option<(Certificate, KeyPair)>> {
+    async fn load_ca(&self) -> Result<Option<Certificate>> {
         let ca_cert_path = self.ca_dir.join("localCA.crt");
         let ca_key_path = self.ca_dir.join("localCA.key");
 
-        if !fs::metadata(&ca_cert_path).await.is_ok() || !fs::metadata(&ca_key_path).await.is_ok() {
+        if fs::metadata(&ca_cert_path).await.is_err() || fs::metadata(&ca_key_path).await.is_err() {
             return Ok(None);
         }
 
@@ -176,11 +226,52 @@ impl CertificateGenerator {
         // Создаем новый CA сертификат с тем же ключом
         let ca_cert = self.create_ca_with_key(ca_key_pair).await?;
 
-        // Создаем новый KeyPair из того же PEM (так как KeyPair не реализует Clone)
-        let ca_key_pair_new = KeyPair::from_pem(&ca_key_pem)
-            .map_err(|e| anyhow!("Failed to parse CA key PEM again: {}", e))?;
+        Ok(Some(ca_cert))
+    }
+
+    /// Load the CA from its files, creating and saving a new one if they are missing
+    async fn load_or_create_ca(&self) -> Result<Certificate> {
+        if let Some(ca_cert) = self.load_ca().await? {
+            return Ok(ca_cert);
+        }
+
+        // Создаем CA сертификат
+        let ca_cert = self.create_ca_certificate().await?;
+
+        // Сохраняем CA сертификат
+        let ca_cert_pem = ca_cert.serialize_pem()?;
+        let ca_key_pem = ca_cert.serialize_private_key_pem();
+
+        fs::write(self.ca_dir.join("localCA.crt"), &ca_cert_pem).await?;
+        fs::write(self.ca_dir.join("localCA.key"), &ca_key_pem).await?;
+
+        Ok(ca_cert)
+    }
+
+    /// Get the shared CA certificate. The common case only takes the read lock; the
+    /// write lock is held while loading or creating the CA, so it happens exactly once.
+    async fn ca_certificate(&self) -> Result<RwLockReadGuard<'static, Certificate>> {
+        let is_cached = |cache: &Option<CachedCA>| matches!(cache, Some(ca) if ca.ca_dir == self.ca_dir);
 
-        Ok(Some((ca_cert, ca_key_pair_new)))
+        let cache = CA_CACHE.read().await;
+        let cache = if is_cached(&cache) {
+            cache
+        } else {
+            drop(cache);
+
+            let mut cache = CA_CACHE.write().await;
+            // Another task may have filled the cache while we waited for the write lock
+            if !is_cached(&cache) {
+                *cache = Some(CachedCA {
+                    ca_dir: self.ca_dir.clone(),
+                    certificate: self.load_or_create_ca().await?,
+                });
+            }
+            cache.downgrade()
+        };
+
+        RwLockReadGuard::try_map(cache, |ca| ca.as_ref().map(|ca| &ca.certificate))
+            .map_err(|_| anyhow!("CA certificate is not available"))
     }
 
     /// Generate certificates for a domain if they don't exist
@@ -198,43 +289,7 @@ impl CertificateGenerator {
         info!("Generating certificates for {}", self.domain);
 
         // Get or create CA certificate
-        let (ca_cert, _ca_key) = if self.has_ca_files().await {
-            match self.load_ca().await? {
-                Some(ca) => ca,
-                None => {
-                    let ca_cert = self.create_ca_certificate().await?;
-
-                    // Сохраняем CA сертификат
-                    let ca_cert_pem = ca_cert.serialize_pem()?;
-                    let ca_key_pem = ca_cert.serialize_private_key_pem();
-
-                    fs::write(self.ca_dir.join("localCA.crt"), &ca_cert_pem).await?;
-                    fs::write(self.ca_dir.join("localCA.key"), &ca_key_pem).await?;
-
-                    // Получаем KeyPair из CA сертификата для подписи
-                    let ca_key_pair = KeyPair::from_pem(&ca_key_pem)
-                        .map_err(|e| anyhow!("Failed to parse generated CA key PEM: {}", e))?;
-
-                    (ca_cert, ca_key_pair)
-                }
-            }
-        } else {
-            // Создаем CA сертификат
-            let ca_cert = self.create_ca_certificate().await?;
-
-            // Сохраняем CA сертификат
-            let ca_cert_pem = ca_cert.serialize_pem()?;
-            let ca_key_pem = ca_cert.serialize_private_key_pem();
-
-            fs::write(self.ca_dir.join("localCA.crt"), &ca_cert_pem).await?;
-            fs::write(self.ca_dir.join("localCA.key"), &ca_key_pem).await?;
-
-            // Получаем KeyPair из CA сертификата для подписи
-            let ca_key_pair = KeyPair::from_pem(&ca_key_pem)
-                .map_err(|e| anyhow!("Failed to parse generated CA key PEM: {}", e))?;
-
-            (ca_cert, ca_key_pair)
-        };
+        let ca_cert = self.ca_certificate().await?;
 
         // Создаем сертификат домена
         let domain_cert = self.create_domain_certificate().await?;
@@ -248,6 +303,7 @@ impl CertificateGenerator {
         // Создаем цепочку сертификатов
         let ca_cert_pem = ca_cert.serialize_pem()?;
         let chain_pem = format!("{}\n{}", cert_pem, ca_cert_pem);
+        drop(ca_cert);
 
         // Сохраняем файлы сертификатов
         fs::write(
@@ -267,7 +323,213 @@ impl CertificateGenerator {
         )
         .await?;
 
+        // The PEM files are what NGINX needs, so a failed export (e.g. no openssl binary) is not fatal
+        if self.pkcs12_enabled {
+            if let Err(e) = self.generate_pkcs12(&crate::config::get().pkcs12_password).await {
+                warn!("{}; set no_pkcs12 to skip the PKCS#12 bundle", e);
+            }
+        }
+
         info!("Successfully generated certificates for {}", self.domain);
         Ok(())
     }
+
+    /// Delete the domain's certificate files and generate them again against the existing CA
+    pub async fn regenerate_certificates(&self) -> Result<()> {
+        for suffix in ["crt", "key", "fullchain.crt", "p12"] {
+            let path = self.certs_dir.join(format!("{}.{}", self.domain, suffix));
+            match fs::remove_file(&path).await {
+                Ok(()) => debug!("Removed {}", path.display()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!("Failed to remove {}: {}", path.display(), e)),
+            }
+        }
+
+        self.generate_certificates().await
+    }
+
+    /// SHA-256 fingerprint of the domain certificate as colon-separated hex, e.g. `AA:BB:...`
+    pub async fn fingerprint(&self) -> Result<String> {
+        let cert_path = self.certs_dir.join(format!("{}.crt", self.domain));
+        let pem = fs::read_to_string(&cert_path)
+            .await
+            .map_err(|e| anyhow!("Failed to read {}: {}", cert_path.display(), e))?;
+
+        pem_fingerprint(&pem).map_err(|e| anyhow!("Invalid certificate {}: {}", cert_path.display(), e))
+    }
+
+    /// Copy a client CA into the certificates directory as `<domain>.client-ca.crt`,
+    /// where the NGINX container can read it
+    pub async fn install_client_ca(&self, source: &Path) -> Result<()> {
+        fs::create_dir_all(&self.certs_dir).await?;
+
+        let target = self.certs_dir.join(format!("{}.client-ca.crt", self.domain));
+        fs::copy(source, &target)
+            .await
+            .map_err(|e| anyhow!("Failed to copy client CA {}: {}", source.display(), e))?;
+
+        debug!("Installed client CA for {} from {}", self.domain, source.display());
+        Ok(())
+    }
+
+    /// Combine the generated domain certificate, key and CA chain into `<domain>.p12`
+    /// for tools that only import PKCS#12 (Java keystores, Windows certificate import)
+    pub async fn generate_pkcs12(&self, password: &str) -> Result<()> {
+        let cert_path = self.certs_dir.join(format!("{}.crt", self.domain));
+        let key_path = self.certs_dir.join(format!("{}.key", self.domain));
+        let ca_path = self.ca_dir.join("localCA.crt");
+        let p12_path = self.certs_dir.join(format!("{}.p12", self.domain));
+
+        // Pass the passphrase through the environment to keep it out of the process list
+        let output = Command::new("openssl")
+            .arg("pkcs12")
+            .arg("-export")
+            .arg("-in")
+            .arg(&cert_path)
+            .arg("-inkey")
+            .arg(&key_path)
+            .arg("-certfile")
+            .arg(&ca_path)
+            .arg("-name")
+            .arg(&self.domain)
+            .arg("-out")
+            .arg(&p12_path)
+            .args(["-passout", "env:AUTOLOCALHOST_PKCS12_PASSOUT"])
+            .env("AUTOLOCALHOST_PKCS12_PASSOUT", password)
+            .output()
+            .await
+            .map_err(|e| anyhow!("Failed to run openssl for PKCS#12 export: {}", e))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Failed to create PKCS#12 bundle for {}: {}",
+                self.domain,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        info!("Generated PKCS#12 bundle: {}", p12_path.display());
+        Ok(())
+    }
+}
+
+/// Fingerprint the DER bytes of the first certificate in a PEM file
+fn pem_fingerprint(pem: &str) -> Result<String> {
+    let body: String = pem
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "-----BEGIN CERTIFICATE-----")
+        .skip(1)
+        .take_while(|line| *line != "-----END CERTIFICATE-----")
+        .collect();
+    if body.is_empty() {
+        return Err(anyhow!("no PEM certificate found"));
+    }
+
+    let der = base64::engine::general_purpose::STANDARD.decode(body)?;
+    Ok(Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// Only apex-like names (`app.test`) get a `www.` SAN; `www.api.app.test` would never be used
+fn has_www_alias(domain: &str) -> bool {
+    domain.split('.').count() <= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    /// The to-be-signed part of a PEM certificate. ECDSA signatures differ on every
+    /// serialization, so certificates are compared without them.
+    fn tbs_certificate(pem: &str) -> Vec<u8> {
+        let body: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+        let der = base64::engine::general_purpose::STANDARD.decode(body).unwrap();
+
+        // Skip the outer SEQUENCE header, then take the inner SEQUENCE with its header
+        let header_len = |der: &[u8]| if der[1] & 0x80 == 0 { 2 } else { 2 + (der[1] & 0x7f) as usize };
+        let content_len = |der: &[u8]| {
+            if der[1] & 0x80 == 0 {
+                der[1] as usize
+            } else {
+                der[2..header_len(der)].iter().fold(0, |len, byte| (len << 8) | *byte as usize)
+            }
+        };
+
+        let tbs = &der[header_len(&der)..];
+        tbs[..header_len(tbs) + content_len(tbs)].to_vec()
+    }
+
+    #[test]
+    fn adds_extra_ips_after_loopback_without_duplicates() {
+        let ips: Vec<IpAddr> = ["127.0.0.2", "127.0.0.1", "::1"].iter().map(|ip| ip.parse().unwrap()).collect();
+        let generator = CertificateGenerator::with_dirs("app.test", PathBuf::new(), PathBuf::new())
+            .with_extra_ips(ips);
+
+        let sans: Vec<String> = generator.ip_sans().iter().map(IpAddr::to_string).collect();
+        assert_eq!(sans, vec!["127.0.0.1", "127.0.0.2", "::1"]);
+    }
+
+    #[test]
+    fn fingerprints_the_der_bytes_of_a_pem_certificate() {
+        let pem = "-----BEGIN CERTIFICATE-----\nYWJj\n-----END CERTIFICATE-----\n";
+        assert_eq!(
+            pem_fingerprint(pem).unwrap(),
+            "BA:78:16:BF:8F:01:CF:EA:41:41:40:DE:5D:AE:22:23:B0:03:61:A3:96:17:7A:9C:B4:10:FF:61:F2:00:15:AD"
+        );
+        assert!(pem_fingerprint("not a certificate").is_err());
+    }
+
+    #[test]
+    fn adds_www_san_to_apex_domains_only() {
+        assert!(has_www_alias("app.test"));
+        assert!(has_www_alias("localapp"));
+        assert!(!has_www_alias("api.app.test"));
+        assert!(!has_www_alias("www.app.test"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_generation_creates_a_single_ca() {
+        let work_dir = std::env::temp_dir().join(format!("autolocalhost-ca-test-{}", std::process::id()));
+        let certs_dir = work_dir.join("certs");
+        let ca_dir = work_dir.join("ca");
+
+        let tasks: Vec<_> = (0..10)
+            .map(|i| {
+                let generator = CertificateGenerator::with_dirs(
+                    &format!("app{}.test", i),
+                    certs_dir.clone(),
+                    ca_dir.clone(),
+                );
+                tokio::spawn(async move { generator.generate_certificates().await })
+            })
+            .collect();
+
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        // Every chain must end with the same CA certificate
+        let mut ca_pems = Vec::new();
+        for i in 0..10 {
+            let chain = fs::read_to_string(certs_dir.join(format!("app{}.test.fullchain.crt", i))).await.unwrap();
+            let (_, ca_pem) = chain.split_once("-----END CERTIFICATE-----").unwrap();
+            ca_pems.push(tbs_certificate(ca_pem));
+        }
+        ca_pems.dedup();
+        assert_eq!(ca_pems.len(), 1);
+
+        // ...and that certificate must be signed by the key saved on disk
+        let key_pem = fs::read_to_string(ca_dir.join("localCA.key")).await.unwrap();
+        let cached = CA_CACHE.read().await;
+        let cached = cached.as_ref().unwrap();
+        assert_eq!(cached.ca_dir, ca_dir);
+        assert_eq!(cached.certificate.serialize_private_key_pem(), key_pem);
+
+        let _ = fs::remove_dir_all(&work_dir).await;
+    }
 }
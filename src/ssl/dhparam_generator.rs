@@ -1,20 +1,39 @@
 use anyhow::Result;
-use log::{debug, info};
+use base64::Engine;
+use log::{debug, info, warn};
 use tokio::fs;
 use tokio::process::Command;
 
-/// Generate DH parameters for SSL
+/// Bit size of the bundled fallback parameters
+const FALLBACK_DHPARAM_BITS: u32 = 2048;
+
+/// Generate DH parameters for SSL, regenerating them when the configured size changed
 pub async fn generate_dhparam_if_needed() -> Result<()> {
+    if crate::config::get().dhparam_skip {
+        info!("DH parameter generation is disabled, SSL servers will not use ssl_dhparam");
+        return Ok(());
+    }
+
     let certs_dir = crate::installer::get_certs_dir();
     let dhparam_path = certs_dir.join("dhparams.crt");
+    let bits = configured_bits();
 
-    // Check if file already exists
-    if dhparam_path.exists() {
-        debug!("DH parameters file already exists");
-        return Ok(());
+    // Check if file already exists with the configured size
+    if let Ok(pem) = fs::read_to_string(&dhparam_path).await {
+        match dhparam_bits(&pem) {
+            Some(existing) if existing == bits => {
+                debug!("DH parameters file already exists ({} bit)", existing);
+                return Ok(());
+            }
+            Some(existing) => info!("Existing DH parameters are {} bit, regenerating with {} bit", existing, bits),
+            None => warn!("Could not read the size of {}, regenerating it", dhparam_path.display()),
+        }
     }
 
-    info!("Generating DH parameters (this may take a while)...");
+    info!("Generating {}-bit DH parameters (this may take a while)...", bits);
+    if bits >= 4096 {
+        warn!("Generating 4096-bit DH parameters can take several minutes");
+    }
 
     // Ensure certs directory exists
     fs::create_dir_all(&certs_dir).await?;
@@ -22,7 +41,7 @@ pub async fn generate_dhparam_if_needed() -> Result<()> {
     // Try to use openssl command to generate DH params
     let dhparam_str = dhparam_path.to_string_lossy();
     let output = Command::new("openssl")
-        .args(["dhparam", "-out", &dhparam_str, "2048"])
+        .args(["dhparam", "-out", &dhparam_str, &bits.to_string()])
         .output()
         .await;
 
@@ -39,7 +58,7 @@ pub async fn generate_dhparam_if_needed() -> Result<()> {
                 info!("Failed to generate DH parameters: {}", error);
 
                 // Provide a basic DH params file as fallback
-                info!("Using pre-generated DH parameters as fallback");
+                info!("Using pre-generated {}-bit DH parameters as fallback", FALLBACK_DHPARAM_BITS);
                 let default_dhparams = include_bytes!("../../assets/dhparams.crt");
                 fs::write(&dhparam_path, default_dhparams).await?;
                 info!(
@@ -53,7 +72,7 @@ pub async fn generate_dhparam_if_needed() -> Result<()> {
             info!("OpenSSL command failed: {}", e);
 
             // Provide a basic DH params file as fallback
-            info!("Using pre-generated DH parameters as fallback");
+            info!("Using pre-generated {}-bit DH parameters as fallback", FALLBACK_DHPARAM_BITS);
             let default_dhparams = include_bytes!("../../assets/dhparams.crt");
             fs::write(&dhparam_path, default_dhparams).await?;
             info!(
@@ -64,3 +83,67 @@ pub async fn generate_dhparam_if_needed() -> Result<()> {
         }
     }
 }
+
+/// DH parameter size from the configuration, limited to the supported sizes
+fn configured_bits() -> u32 {
+    match crate::config::get().dhparam_bits {
+        1024 => {
+            warn!("1024-bit DH parameters are considered weak; use 2048 or 4096 outside of testing");
+            1024
+        }
+        bits @ (2048 | 4096) => bits,
+        other => {
+            warn!("Unsupported DH parameter size {} (allowed: 1024, 2048, 4096), using 2048", other);
+            2048
+        }
+    }
+}
+
+/// Size in bits of the prime in a PEM `DH PARAMETERS` block (`SEQUENCE { INTEGER p, INTEGER g }`)
+fn dhparam_bits(pem: &str) -> Option<u32> {
+    let body: String = pem
+        .lines()
+        .skip_while(|line| !line.starts_with("-----BEGIN DH PARAMETERS-----"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("-----END"))
+        .collect();
+    let der = base64::engine::general_purpose::STANDARD.decode(body).ok()?;
+
+    // Skip the SEQUENCE header, then read the INTEGER holding the prime
+    let (sequence, _) = der_element(&der, 0x30)?;
+    let (prime, _) = der_element(sequence, 0x02)?;
+
+    let first = prime.iter().position(|&byte| byte != 0)?;
+    let significant = &prime[first..];
+    Some(significant.len() as u32 * 8 - significant[0].leading_zeros())
+}
+
+/// Split a DER element with the expected tag into its content and the bytes after it
+fn der_element(der: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    if *der.first()? != tag {
+        return None;
+    }
+
+    let first_len = *der.get(1)?;
+    let (len, header_len) = if first_len & 0x80 == 0 {
+        (first_len as usize, 2)
+    } else {
+        let len_bytes = (first_len & 0x7f) as usize;
+        let len = der.get(2..2 + len_bytes)?.iter().fold(0usize, |len, &byte| (len << 8) | byte as usize);
+        (len, 2 + len_bytes)
+    };
+
+    let content = der.get(header_len..header_len + len)?;
+    Some((content, &der[header_len + len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_size_of_bundled_dhparams() {
+        let pem = include_str!("../../assets/dhparams.crt");
+        assert_eq!(dhparam_bits(pem), Some(FALLBACK_DHPARAM_BITS));
+    }
+}
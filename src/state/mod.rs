@@ -0,0 +1,111 @@
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use crate::docker::container_info::ContainerInfo;
+use crate::installer::get_data_dir;
+use crate::utils::atomic_file::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+const STATE_FILE_NAME: &str = "state.json";
+
+/// Outcome of the last reconfiguration, kept in the data directory for external tools
+/// and the status command
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceState {
+    pub version: String,
+    /// RFC 3339 time of the reconfiguration
+    pub updated_at: String,
+    pub containers: Vec<RoutedContainer>,
+    /// Hosts file entries: domains and published network aliases
+    pub domains: Vec<String>,
+    pub ports: Vec<u16>,
+    pub warnings: Vec<String>,
+}
+
+/// A container that was routed by the reconfiguration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoutedContainer {
+    pub id: String,
+    pub name: String,
+    pub domain: String,
+    pub ports: Vec<u16>,
+    pub ssl_ports: Vec<u16>,
+    /// IP SANs from the extra_ips label, so regen-cert can keep them without Docker
+    #[serde(default)]
+    pub extra_ips: Vec<IpAddr>,
+}
+
+impl ServiceState {
+    pub fn new(containers: &[ContainerInfo], domains: Vec<String>, ports: Vec<u16>, warnings: Vec<String>) -> Self {
+        let mut containers: Vec<RoutedContainer> = containers
+            .iter()
+            .map(|container| RoutedContainer {
+                id: container.id.clone(),
+                name: container.name.clone(),
+                domain: container.domain.clone(),
+                ports: container.ports.iter().map(|port| port.external).collect(),
+                ssl_ports: container.ssl_ports.iter().map(|port| port.external).collect(),
+                extra_ips: container.extra_ips.clone(),
+            })
+            .collect();
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut ports = ports;
+        ports.sort_unstable();
+
+        Self {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            updated_at: Utc::now().to_rfc3339(),
+            containers,
+            domains,
+            ports,
+            warnings,
+        }
+    }
+}
+
+pub fn get_state_file_path() -> PathBuf {
+    get_data_dir().join(STATE_FILE_NAME)
+}
+
+/// Replace the state file atomically, so readers never see a partial file
+pub async fn write(state: &ServiceState) -> Result<()> {
+    let json = serde_json::to_string_pretty(state)?;
+    write_atomic(&get_state_file_path(), json.as_bytes()).await
+}
+
+pub async fn read() -> Result<ServiceState> {
+    let path = get_state_file_path();
+    let json = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&json).map_err(|e| anyhow!("Invalid state file {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::port_mapping::PortMapping;
+
+    #[test]
+    fn lists_routed_containers_and_sorted_ports() {
+        let containers = vec![ContainerInfo {
+            id: String::from("web-id"),
+            name: String::from("web"),
+            domain: String::from("web.test"),
+            ports: vec![PortMapping::new(80, 3000)],
+            ssl_ports: vec![PortMapping::new(443, 3000)],
+            extra_ips: vec![IpAddr::from([127, 0, 0, 2])],
+            ..Default::default()
+        }];
+
+        let state = ServiceState::new(&containers, vec![String::from("web.test")], vec![443, 80], Vec::new());
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+
+        assert_eq!(json["ports"], serde_json::json!([80, 443]));
+        assert_eq!(json["containers"][0]["domain"], "web.test");
+        assert_eq!(json["containers"][0]["ssl_ports"], serde_json::json!([443]));
+        assert_eq!(json["containers"][0]["extra_ips"], serde_json::json!(["127.0.0.2"]));
+    }
+}
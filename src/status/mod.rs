@@ -0,0 +1,86 @@
+use anyhow::Result;
+use bollard::errors::Error as BollardError;
+use crate::nginx::container_manager::ContainerManager;
+use crate::ssl::certificate_generator::CertificateGenerator;
+
+/// Print the state of the managed NGINX container and its image
+pub async fn run() -> Result<()> {
+    println!("autolocalhost {}", env!("CARGO_PKG_VERSION"));
+
+    let docker = match crate::docker::try_connect_docker().await {
+        Ok(docker) => docker,
+        Err(e) => {
+            println!("Docker: unavailable ({})", e);
+            return Ok(());
+        }
+    };
+    println!("Docker: connected");
+
+    let nginx_manager = ContainerManager::new(docker.clone());
+
+    let container_state = match docker.inspect_container(nginx_manager.container_name(), None).await {
+        Ok(details) => details
+            .state
+            .and_then(|state| state.status)
+            .map(|status| status.to_string())
+            .unwrap_or_else(|| String::from("unknown")),
+        Err(BollardError::DockerResponseServerError { status_code: 404, .. }) => String::from("not created"),
+        Err(e) => format!("error ({})", e),
+    };
+    println!("NGINX container: {} ({})", nginx_manager.container_name(), container_state);
+
+    println!("NGINX image: {}", nginx_manager.image());
+    match nginx_manager.image_digest().await {
+        Ok(Some(digest)) => println!("NGINX image digest: {}", digest),
+        Ok(None) => println!("NGINX image digest: none (locally built image)"),
+        Err(e) => println!("NGINX image digest: error ({})", e),
+    }
+
+    match nginx_manager.list_containers_in_network().await {
+        Ok(names) => {
+            println!("Network {}: {} container(s) reachable from NGINX", nginx_manager.network_name(), names.len());
+            for name in names {
+                println!("  - {}", name);
+            }
+        }
+        Err(e) if matches!(
+            e.downcast_ref::<BollardError>(),
+            Some(BollardError::DockerResponseServerError { status_code: 404, .. })
+        ) => println!("Network {}: not created", nginx_manager.network_name()),
+        Err(e) => println!("Network {}: error ({})", nginx_manager.network_name(), e),
+    }
+
+    print_last_reconfiguration().await;
+    Ok(())
+}
+
+/// Summarize the state file written by the service after each reconfiguration
+async fn print_last_reconfiguration() {
+    let state = match crate::state::read().await {
+        Ok(state) => state,
+        Err(e) => {
+            println!("Last reconfiguration: unknown ({})", e);
+            return;
+        }
+    };
+
+    println!("Last reconfiguration: {} (autolocalhost {})", state.updated_at, state.version);
+    let ports = if state.ports.is_empty() {
+        String::from("none")
+    } else {
+        state.ports.iter().map(|port| port.to_string()).collect::<Vec<_>>().join(", ")
+    };
+    println!("Routed: {} container(s), {} domain(s), ports: {}", state.containers.len(), state.domains.len(), ports);
+    for container in &state.containers {
+        println!("  - {} -> {}", container.domain, container.name);
+        if !container.ssl_ports.is_empty() {
+            match CertificateGenerator::new(&container.domain).fingerprint().await {
+                Ok(fingerprint) => println!("    certificate SHA-256: {}", fingerprint),
+                Err(e) => println!("    certificate: error ({})", e),
+            }
+        }
+    }
+    for warning in &state.warnings {
+        println!("Warning: {}", warning);
+    }
+}
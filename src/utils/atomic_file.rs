@@ -0,0 +1,35 @@
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Write a file atomically: the content goes to a temporary file in the same directory,
+/// is flushed to disk, and is then renamed over the target. Readers see either the old
+/// or the new content, never a partially written file.
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file path: {}", path.display()))?
+        .to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let mut file = fs::File::create(&temp_path).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    if let Err(e) = fs::rename(&temp_path, path).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(anyhow!("Failed to replace {}: {}", path.display(), e));
+    }
+
+    // Persist the rename itself by syncing the parent directory
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use log::debug;
+use std::net::IpAddr;
+use tokio::net::lookup_host;
+use tokio::time::{sleep, timeout, Duration};
+
+const RETRY_INTERVAL_MS: u64 = 200;
+
+/// Resolve a domain through the system resolver and check whether it maps to the expected IP
+pub async fn verify_hosts_entry(domain: &str, expected_ip: IpAddr) -> Result<bool> {
+    let addresses = lookup_host((domain, 0)).await?;
+
+    for address in addresses {
+        if address.ip() == expected_ip {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Poll the resolver until the domain resolves to the expected IP or the time limit passes
+pub async fn wait_for_hosts_entry(domain: &str, expected_ip: IpAddr, limit: Duration) -> bool {
+    let result = timeout(limit, async {
+        loop {
+            match verify_hosts_entry(domain, expected_ip).await {
+                Ok(true) => return,
+                Ok(false) => debug!("{} does not resolve to {} yet", domain, expected_ip),
+                Err(e) => debug!("Failed to resolve {}: {}", domain, e),
+            }
+            sleep(Duration::from_millis(RETRY_INTERVAL_MS)).await;
+        }
+    })
+    .await;
+
+    result.is_ok()
+}
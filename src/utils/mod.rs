@@ -1 +1,4 @@
+pub mod atomic_file;
+pub mod dns_resolver;
 pub mod port_mapping;
+pub mod tar_archive;
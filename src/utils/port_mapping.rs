@@ -1,6 +1,9 @@
 use anyhow::{Result, anyhow};
 use serde::{Serialize, Deserialize};
 use log::debug;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
 
 /// Port mapping structure to handle internal/external port mappings
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +18,11 @@ impl PortMapping {
         Self { external, internal }
     }
 
+    /// Host address the external port is published on (all interfaces)
+    pub fn to_socket_addr(&self) -> SocketAddr {
+        SocketAddr::from(([0, 0, 0, 0], self.external))
+    }
+
     /// Parse a single port mapping string (e.g., "8080" or "8080:80")
     pub fn parse_port_mapping(mapping_str: &str) -> Result<Self> {
         let trimmed = mapping_str.trim();
@@ -73,3 +81,67 @@ impl PortMapping {
     }
 }
 
+/// External ports that are already taken on the host, found by trying to bind each one.
+/// Other bind errors (e.g. missing privileges for ports below 1024) are not reported as conflicts.
+pub async fn check_port_conflicts(mappings: &[PortMapping]) -> Vec<u16> {
+    let mut conflicts = Vec::new();
+
+    for mapping in mappings {
+        match TcpListener::bind(mapping.to_socket_addr()).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::AddrInUse => conflicts.push(mapping.external),
+            Err(e) => debug!("Could not check port {}: {}", mapping.external, e),
+        }
+    }
+
+    conflicts
+}
+
+/// Upstream address given as `<host>:<port>`, e.g. a container name and its port
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpstreamTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+impl UpstreamTarget {
+    /// Parse an upstream target string (e.g., "backup-app:8080")
+    pub fn parse(target_str: &str) -> Result<Self> {
+        let trimmed = target_str.trim();
+
+        let (host, port) = trimmed.rsplit_once(':')
+            .ok_or_else(|| anyhow!("Invalid upstream format (expected <host>:<port>): {}", trimmed))?;
+
+        let valid_host = !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if !valid_host {
+            return Err(anyhow!("Invalid upstream host: {}", host));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port: PortMapping::validate_port(port)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reports_free_port_as_available() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        assert!(check_port_conflicts(&[PortMapping::new(port, 80)]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_port_in_use_as_conflict() {
+        let listener = TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert_eq!(check_port_conflicts(&[PortMapping::new(port, 80)]).await, vec![port]);
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::{Result, anyhow};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BLOCK_SIZE: usize = 512;
+const NAME_FIELD_LEN: usize = 100;
+
+/// Build an uncompressed ustar archive holding one directory and the given files inside it,
+/// in the format the Docker archive API (`upload_to_container`) expects
+pub fn directory_with_files(dir: &str, files: &[(&str, &[u8])]) -> Result<Vec<u8>> {
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let mut archive = Vec::new();
+
+    archive.extend_from_slice(&header(&format!("{}/", dir), 0, 0o755, b'5', mtime)?);
+
+    for (name, contents) in files {
+        archive.extend_from_slice(&header(&format!("{}/{}", dir, name), contents.len(), 0o644, b'0', mtime)?);
+        archive.extend_from_slice(contents);
+
+        // File data is padded to a whole number of blocks
+        let padding = (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        archive.resize(archive.len() + padding, 0);
+    }
+
+    // Two zero blocks mark the end of the archive
+    archive.resize(archive.len() + 2 * BLOCK_SIZE, 0);
+    Ok(archive)
+}
+
+fn header(path: &str, size: usize, mode: u32, type_flag: u8, mtime: u64) -> Result<[u8; BLOCK_SIZE]> {
+    if path.len() > NAME_FIELD_LEN {
+        return Err(anyhow!("Archive path is longer than {} bytes: {}", NAME_FIELD_LEN, path));
+    }
+
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    write_octal(&mut header[100..108], mode as u64);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size as u64);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&byte| byte as u64).sum();
+    write_octal(&mut header[148..155], checksum);
+
+    Ok(header)
+}
+
+/// Zero-padded octal number terminated by NUL, filling the whole field
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_padded_archive_with_valid_checksums() {
+        let archive = directory_with_files("autolocalhost", &[("nginx.conf", b"events {}\n")]).unwrap();
+
+        // Directory header, file header, one data block, two end blocks
+        assert_eq!(archive.len(), 5 * BLOCK_SIZE);
+
+        let file_header = &archive[BLOCK_SIZE..2 * BLOCK_SIZE];
+        assert!(file_header.starts_with(b"autolocalhost/nginx.conf\0"));
+        assert_eq!(&file_header[124..136], b"00000000012\0");
+        assert_eq!(&archive[2 * BLOCK_SIZE..2 * BLOCK_SIZE + 10], b"events {}\n");
+
+        let mut unsigned = file_header.to_vec();
+        unsigned[148..156].fill(b' ');
+        let expected: u64 = unsigned.iter().map(|&byte| byte as u64).sum();
+        let stored = std::str::from_utf8(&file_header[148..154]).unwrap();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), expected);
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow};
+use bollard::container::InspectContainerOptions;
+use bollard::Docker;
+use crate::docker::container_info::ContainerInfo;
+use crate::nginx::config_generator::ConfigGenerator;
+use crate::ssl::certificate_generator::CertificateGenerator;
+use crate::utils::port_mapping::PortMapping;
+use std::collections::HashMap;
+
+/// Result of checking a single label
+#[derive(Debug, PartialEq)]
+enum Check {
+    Ok(String),
+    Warn(String),
+    Fail(String),
+}
+
+/// Inspect a container and report whether its core labels are present and parse the way
+/// the service would parse them. Fails if any label is invalid.
+pub async fn run(container: &str) -> Result<()> {
+    let docker = crate::docker::try_connect_docker().await
+        .map_err(|e| anyhow!("Docker is not available: {}", e))?;
+
+    let details = docker.inspect_container(container, None::<InspectContainerOptions>).await
+        .map_err(|e| anyhow!("Failed to inspect container {}: {}", container, e))?;
+    let labels = details.config.and_then(|config| config.labels).unwrap_or_default();
+
+    println!("Labels of {}:", container);
+    let checks = check_labels(&labels);
+    for check in &checks {
+        match check {
+            Check::Ok(message) => println!("[ ok ] {}", message),
+            Check::Warn(message) => println!("[warn] {}", message),
+            Check::Fail(message) => println!("[fail] {}", message),
+        }
+    }
+
+    let failures = checks.iter().filter(|check| matches!(check, Check::Fail(_))).count();
+    if failures > 0 {
+        return Err(anyhow!("{} label error(s) on {}", failures, container));
+    }
+
+    println!("\nLabels of {} are valid", container);
+    print_config_lints(&docker, container).await;
+
+    let label = |name: &str| labels.get(&crate::config::label(name)).map(|v| v.as_str());
+    if let (Some("true"), Some(domain)) = (label("sslEnabled"), label("domain")) {
+        match CertificateGenerator::new(domain).fingerprint().await {
+            Ok(fingerprint) => println!("Certificate of {} SHA-256: {}", domain, fingerprint),
+            Err(_) => println!("Certificate of {}: not generated yet", domain),
+        }
+    }
+    Ok(())
+}
+
+/// Render the NGINX config the service would generate for this container alone and print
+/// the linter's findings for it
+async fn print_config_lints(docker: &Docker, container: &str) {
+    let lints = match ContainerInfo::from_container(docker, container).await {
+        Ok(info) => ConfigGenerator::new(&[info]).render_with_lints().await.map(|(_, lints)| lints),
+        Err(e) => Err(e),
+    };
+
+    match lints {
+        Ok(lints) => {
+            for lint in lints {
+                println!("NGINX config lint ({}): {}", lint.severity, lint.message);
+            }
+        }
+        Err(e) => println!("NGINX config not linted: {}", e),
+    }
+}
+
+/// Check the `enabled`, `domain`, `ports`, `sslEnabled` and `sslPorts` labels
+fn check_labels(labels: &HashMap<String, String>) -> Vec<Check> {
+    let label = |name: &str| labels.get(&crate::config::label(name)).map(|v| v.as_str());
+    let mut checks = Vec::new();
+
+    checks.push(match label("enabled") {
+        Some("true") => Check::Ok(String::from("enabled label is 'true'")),
+        Some(value) => Check::Fail(format!("enabled label '{}' must be 'true' for the container to be routed", value)),
+        None => Check::Fail(format!("enabled label is missing, add {}=true", crate::config::label("enabled"))),
+    });
+
+    checks.push(match label("domain") {
+        Some(domain) if domain.trim().is_empty() => Check::Fail(String::from("domain label is empty")),
+        Some(domain) if domain.chars().any(char::is_whitespace) => {
+            Check::Fail(format!("domain label '{}' must not contain whitespace", domain))
+        }
+        Some(domain) => Check::Ok(format!("domain label is '{}'", domain)),
+        None => Check::Fail(String::from("domain label is missing")),
+    });
+
+    checks.push(match label("ports") {
+        Some(ports) => check_port_mappings("ports", ports),
+        None => Check::Warn(String::from("ports label is missing, the lowest exposed port is routed on port 80")),
+    });
+
+    let ssl_enabled = match label("sslEnabled") {
+        Some("true") => {
+            checks.push(Check::Ok(String::from("sslEnabled label is 'true'")));
+            true
+        }
+        Some("false") | None => false,
+        Some(value) => {
+            checks.push(Check::Fail(format!("sslEnabled label '{}' must be 'true' or 'false'", value)));
+            false
+        }
+    };
+
+    match (label("sslPorts"), ssl_enabled) {
+        (Some(ports), true) => checks.push(check_port_mappings("sslPorts", ports)),
+        (None, true) => checks.push(Check::Warn(String::from("sslPorts label is missing, no HTTPS server is generated"))),
+        (Some(_), false) => checks.push(Check::Warn(String::from("sslPorts label is ignored because sslEnabled is not 'true'"))),
+        (None, false) => {}
+    }
+
+    checks
+}
+
+fn check_port_mappings(name: &str, value: &str) -> Check {
+    match PortMapping::parse_port_mappings(value) {
+        Ok(mappings) if mappings.is_empty() => Check::Warn(format!("{} label is empty", name)),
+        Ok(mappings) => {
+            let routes: Vec<String> = mappings.iter().map(|m| format!("{} -> {}", m.external, m.internal)).collect();
+            Check::Ok(format!("{} label routes {}", name, routes.join(", ")))
+        }
+        Err(e) => Check::Fail(format!("{} label '{}' is not a valid port mapping: {}", name, value, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_invalid_port_labels() {
+        let labels: HashMap<String, String> = [
+            ("enabled", "true"),
+            ("domain", "app.local"),
+            ("ports", "abc"),
+            ("sslEnabled", "true"),
+            ("sslPorts", "443:8443"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (crate::config::label(name), value.to_string()))
+        .collect();
+
+        let checks = check_labels(&labels);
+        let failures: Vec<_> = checks.iter().filter(|check| matches!(check, Check::Fail(_))).collect();
+
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(failures[0], Check::Fail(message) if message.starts_with("ports label 'abc' is not a valid port mapping")));
+        assert!(checks.contains(&Check::Ok(String::from("sslPorts label routes 443 -> 8443"))));
+    }
+}